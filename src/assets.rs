@@ -1,6 +1,11 @@
 use bevy::{asset::Asset, ecs::all_tuples, gltf::Gltf, prelude::*};
 use bevy_asset_loader::prelude::{AssetCollection, LoadingState, LoadingStateAppExt};
 
+use crate::game::{
+    archetypes::{ZombieArchetypes, ZombieArchetypesLoader},
+    balance::{Balance, BalanceLoader},
+};
+
 pub(crate) trait CloneWeak {
     fn clone_weak(&self) -> Self;
 }
@@ -43,12 +48,17 @@ pub(crate) struct AssetPlugin;
 
 impl Plugin for AssetPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_state(AllTheLoading::Assets)
+        app.add_asset::<ZombieArchetypes>()
+            .init_asset_loader::<ZombieArchetypesLoader>()
+            .add_asset::<Balance>()
+            .init_asset_loader::<BalanceLoader>()
+            .add_state(AllTheLoading::Assets)
             .add_loading_state(
                 LoadingState::new(AllTheLoading::Assets)
                     .continue_to_state(AllTheLoading::Pipelines)
                     .with_collection::<RawUiAssets>()
                     .with_collection::<ZombieAssets>()
+                    .with_collection::<BalanceAssets>()
                     .with_collection::<BuildingAssets>()
                     .with_collection::<RawSceneryAssets>(),
             )
@@ -80,6 +90,14 @@ pub(crate) struct ZombieAssets {
     pub(crate) animations: Handle<Gltf>,
     #[asset(path = "zombies/model.glb#Scene0")]
     pub(crate) zombie: Handle<Scene>,
+    #[asset(path = "zombies/archetypes.zombies.ron")]
+    pub(crate) archetypes: Handle<ZombieArchetypes>,
+}
+
+#[derive(AssetCollection)]
+pub(crate) struct BalanceAssets {
+    #[asset(path = "game.balance.ron")]
+    pub(crate) balance: Handle<Balance>,
 }
 
 #[derive(AssetCollection)]
@@ -159,6 +177,17 @@ fn done(world: &mut World) {
             });
         }
 
+        {
+            let balance_assets = world.get_resource::<BalanceAssets>().unwrap();
+            let balance = world
+                .get_resource::<Assets<Balance>>()
+                .unwrap()
+                .get(&balance_assets.balance)
+                .unwrap()
+                .clone();
+            world.insert_resource(balance);
+        }
+
         {
             let zombie_assets = world.get_resource_unchecked_mut::<ZombieAssets>().unwrap();
             let mut scenes = world.get_resource_unchecked_mut::<Assets<Scene>>().unwrap();
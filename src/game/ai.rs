@@ -0,0 +1,249 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+
+use crate::{assets::BuildingAssets, GameState};
+
+use super::{
+    balance::Balance,
+    builder::{blocks_a_nest, place_tower},
+    heightmap::TerrainConfig,
+    nests::ZombieNest,
+    stats::Stats,
+    terra::Plane,
+    terrain_spawner::{map_to_world, FilledLot, Map, NavmeshChanged, Pathfinding},
+    timestep::{SimulationStage, SimulationTime},
+    towers::{Tower, TowerKind},
+    zombies::Zombie,
+    PlayingState,
+};
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::new()
+                .with_run_criteria(ai_playing)
+                .with_system(give_back_control),
+        )
+        // decisions follow the simulation, for a run to play the same whatever the frame rate
+        .add_system_set_to_stage(
+            SimulationStage,
+            SystemSet::new()
+                .with_run_criteria(ai_playing)
+                .with_system(spend_credits)
+                .with_system(switch_plane),
+        )
+        .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(stop_attract));
+    }
+}
+
+/// Present when the AI plays instead of the player.
+pub(crate) struct AiPlayer {
+    /// Started from the menu after some time without input, any input gives control back.
+    pub(crate) attract: bool,
+}
+
+/// Kinds of towers built in turn.
+const BUILD_ORDER: [TowerKind; 7] = [
+    TowerKind::Basic,
+    TowerKind::Basic,
+    TowerKind::Slow,
+    TowerKind::Splash,
+    TowerKind::Chain,
+    TowerKind::Sniper,
+    TowerKind::DualPlane,
+];
+/// Towers to build before considering upgrades.
+const TOWERS_BEFORE_UPGRADES: usize = 5;
+/// Seconds between two purchases.
+const SPEND_INTERVAL: f32 = 1.0;
+/// Seconds to stay on a plane before switching again.
+const SWITCH_INTERVAL: f32 = 10.0;
+/// Lots closest to the crystal tried for a tower, as checking that one doesn't cut a nest from the
+/// crystal is costly.
+const BUILD_CANDIDATES: usize = 8;
+/// Zombies further than this from the crystal are not a threat yet.
+const THREAT_DISTANCE: f32 = 3.0;
+
+fn ai_playing(state: Res<State<GameState>>, ai: Option<Res<AiPlayer>>) -> ShouldRun {
+    if ai.is_some() && *state.current() == GameState::Playing {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Build the next tower of [`BUILD_ORDER`] on the free lot closest to the crystal, or upgrade a
+/// tower when it's cheaper, saving credits until it's affordable.
+fn spend_credits(
+    mut commands: Commands,
+    mut map: ResMut<Map>,
+    mut pathfinding: ResMut<Pathfinding>,
+    lots: Query<(Entity, &FilledLot)>,
+    building_assets: Res<BuildingAssets>,
+    nests: Query<&ZombieNest>,
+    mut towers: Query<(Entity, &mut Tower)>,
+    mut stats: ResMut<Stats>,
+    plane: Res<Plane>,
+    playing_state: Res<State<PlayingState>>,
+    config: Res<TerrainConfig>,
+    balance: Res<Balance>,
+    mut navmesh_changes: EventWriter<NavmeshChanged>,
+    time: Res<SimulationTime>,
+    mut cooldown: Local<f32>,
+) {
+    *cooldown -= time.delta_seconds();
+    if *cooldown > 0.0 || *playing_state.current() != PlayingState::Playing {
+        return;
+    }
+    // looking for a lot is costly, only do it once per interval even when nothing is bought
+    *cooldown = SPEND_INTERVAL;
+
+    let count = towers.iter().count();
+    let kind = BUILD_ORDER[count % BUILD_ORDER.len()];
+    let build_cost = balance.tower(kind).cost;
+    let upgrade = towers
+        .iter()
+        .filter_map(|(entity, tower)| tower.upgrade_cost(&balance).map(|cost| (entity, cost)))
+        .min_by_key(|(_, cost)| *cost);
+    let cheapest = upgrade.map_or(build_cost, |(_, cost)| cost.min(build_cost));
+    if stats.credits < cheapest {
+        return;
+    }
+    let prefer_upgrade = matches!(
+        upgrade,
+        Some((_, cost)) if count >= TOWERS_BEFORE_UPGRADES && cost <= build_cost
+    );
+    let lot = if prefer_upgrade {
+        None
+    } else {
+        let nests = nests.iter().collect::<Vec<_>>();
+        closest_buildable_lot(&map, *plane, &pathfinding, &nests, &config)
+    };
+
+    match (upgrade, lot) {
+        (_, Some(lot)) => {
+            if stats.credits >= build_cost {
+                place_tower(
+                    &mut commands,
+                    kind,
+                    *plane,
+                    lot,
+                    &lots,
+                    &building_assets,
+                    &mut map,
+                    &mut pathfinding,
+                    &mut stats,
+                    &config,
+                    &balance,
+                    &mut navmesh_changes,
+                );
+            }
+        }
+        (Some((entity, cost)), None) => {
+            if stats.credits >= cost {
+                stats.credits -= cost;
+                towers.get_mut(entity).unwrap().1.upgrade(&balance);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Free lot of `plane` closest to the crystal, that wouldn't cut a nest from it. Only the
+/// [`BUILD_CANDIDATES`] closest lots are tried.
+fn closest_buildable_lot(
+    map: &Map,
+    plane: Plane,
+    pathfinding: &Pathfinding,
+    nests: &[&ZombieNest],
+    config: &TerrainConfig,
+) -> Option<(IVec2, IVec2)> {
+    let low_def = config.lots_per_tile as i32;
+    let mut candidates = map
+        .lots
+        .iter()
+        .filter(|((_, lot_plane), _)| *lot_plane == plane)
+        .flat_map(|((tile, _), _)| {
+            (0..low_def).flat_map(move |i| (0..low_def).map(move |j| (*tile, IVec2::new(i, j))))
+        })
+        .filter(|coords| map.is_buildable(*coords))
+        .map(|coords| {
+            (
+                coords,
+                map_to_world(coords, config).distance_squared(Vec2::ZERO),
+            )
+        })
+        .collect::<Vec<_>>();
+    // ties are broken by position, for games not to depend on the iteration order of the map
+    candidates.sort_by(|(a, a_distance), (b, b_distance)| {
+        a_distance
+            .total_cmp(b_distance)
+            .then_with(|| (a.0.x, a.0.y, a.1.x, a.1.y).cmp(&(b.0.x, b.0.y, b.1.x, b.1.y)))
+    });
+    candidates
+        .into_iter()
+        .map(|(coords, _)| coords)
+        .take(BUILD_CANDIDATES)
+        .find(|coords| !blocks_a_nest(*coords, pathfinding, nests.iter().copied(), config))
+}
+
+/// Go to the other plane when zombies there get closer to the crystal than on this one, while
+/// fewer towers defend it.
+fn switch_plane(
+    zombies: Query<(&Zombie, &Transform)>,
+    towers: Query<&Tower>,
+    plane: Res<Plane>,
+    mut playing_state: ResMut<State<PlayingState>>,
+    time: Res<SimulationTime>,
+    mut cooldown: Local<f32>,
+) {
+    *cooldown -= time.delta_seconds();
+    if *cooldown > 0.0 || *playing_state.current() != PlayingState::Playing {
+        return;
+    }
+    let threat = |plane: Plane| {
+        zombies
+            .iter()
+            .filter(|(zombie, _)| zombie.plane == plane)
+            .map(|(_, transform)| {
+                let distance = Vec2::new(transform.translation.x, transform.translation.z).length();
+                (THREAT_DISTANCE - distance).max(0.0)
+            })
+            .sum::<f32>()
+    };
+    let defense = |plane: Plane| {
+        towers
+            .iter()
+            .filter(|tower| tower.kind.reaches(tower.plane, plane))
+            .count()
+    };
+    let other = plane.next();
+    if threat(other) > threat(*plane) && defense(other) < defense(*plane) {
+        let _ = playing_state.set(PlayingState::SwitchingPlane);
+        *cooldown = SWITCH_INTERVAL;
+    }
+}
+
+/// Stop the attract mode as soon as the player does something.
+fn give_back_control(
+    ai: Res<AiPlayer>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if ai.attract
+        && (keyboard_input.get_just_pressed().next().is_some()
+            || mouse_button_input.get_just_pressed().next().is_some()
+            || gamepad_input.get_just_pressed().next().is_some())
+    {
+        let _ = state.set(GameState::Menu);
+    }
+}
+
+fn stop_attract(mut commands: Commands, ai: Option<Res<AiPlayer>>) {
+    if ai.map(|ai| ai.attract).unwrap_or(false) {
+        commands.remove_resource::<AiPlayer>();
+    }
+}
@@ -0,0 +1,176 @@
+use bevy::{
+    asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::Deserialize;
+
+/// Kinds of zombies the nests can spawn, loaded from a `.zombies.ron` file.
+#[derive(TypeUuid)]
+#[uuid = "adddfdab-a213-4df3-9f83-52d5c47e465a"]
+pub(crate) struct ZombieArchetypes {
+    pub(crate) archetypes: Vec<ZombieArchetype>,
+}
+
+impl ZombieArchetypes {
+    pub(crate) fn get(&self, name: &str) -> Option<&ZombieArchetype> {
+        self.archetypes
+            .iter()
+            .find(|archetype| archetype.name == name)
+    }
+
+    /// Parse an archetypes file, getting the handle of each scene from `scene`.
+    pub(crate) fn from_ron(
+        bytes: &[u8],
+        mut scene: impl FnMut(&str) -> Handle<Scene>,
+    ) -> Result<Self, ron::Error> {
+        let raw: RawArchetypes = ron::de::from_bytes(bytes)?;
+        Ok(Self {
+            archetypes: raw
+                .archetypes
+                .into_iter()
+                .map(|archetype| ZombieArchetype {
+                    scene: scene(&archetype.scene),
+                    name: archetype.name,
+                    scale: archetype.scale,
+                    health: archetype.health,
+                    speed: archetype.speed,
+                    reward: archetype.reward,
+                    damage: archetype.damage,
+                    tower_damage: archetype.tower_damage,
+                    weight: archetype.weight,
+                    count: archetype.count,
+                    from_wave: archetype.from_wave,
+                })
+                .collect(),
+        })
+    }
+}
+
+pub(crate) struct ZombieArchetype {
+    pub(crate) name: String,
+    pub(crate) scene: Handle<Scene>,
+    pub(crate) scale: f32,
+    /// Life of a zombie spawned during a wave.
+    pub(crate) health: Curve,
+    /// Speed bonus of a zombie spawned after some time in the run.
+    pub(crate) speed: Curve,
+    /// Credits earned when killing one.
+    pub(crate) reward: u32,
+    /// Lives lost when one reaches the crystal.
+    pub(crate) damage: u32,
+    /// Damage per second done to a tower next to it.
+    pub(crate) tower_damage: f32,
+    /// Relative chance to be picked by a nest.
+    pub(crate) weight: u32,
+    /// Number of zombies spawned together.
+    pub(crate) count: u32,
+    /// First wave in which nests can pick it.
+    pub(crate) from_wave: u32,
+}
+
+/// Value growing linearly with the time spent in the run and the number of the wave.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub(crate) struct Curve {
+    #[serde(default)]
+    pub(crate) base: f32,
+    #[serde(default)]
+    pub(crate) per_second: f32,
+    #[serde(default)]
+    pub(crate) per_wave: f32,
+}
+
+impl Curve {
+    pub(crate) fn at(&self, elapsed_secs: f32, wave: u32) -> f32 {
+        self.base + self.per_second * elapsed_secs + self.per_wave * wave as f32
+    }
+}
+
+#[derive(Deserialize)]
+struct RawArchetypes {
+    archetypes: Vec<RawArchetype>,
+}
+
+#[derive(Deserialize)]
+struct RawArchetype {
+    name: String,
+    scene: String,
+    scale: f32,
+    health: Curve,
+    speed: Curve,
+    reward: u32,
+    damage: u32,
+    #[serde(default)]
+    tower_damage: f32,
+    weight: u32,
+    #[serde(default = "one")]
+    count: u32,
+    #[serde(default)]
+    from_wave: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Default)]
+pub(crate) struct ZombieArchetypesLoader;
+
+impl AssetLoader for ZombieArchetypesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let mut dependencies = vec![];
+            let archetypes = ZombieArchetypes::from_ron(bytes, |scene| {
+                let scene_path = AssetPath::from(scene).to_owned();
+                dependencies.push(scene_path.clone());
+                load_context.get_handle(scene_path)
+            })?;
+            load_context
+                .set_default_asset(LoadedAsset::new(archetypes).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["zombies.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Handle;
+
+    use super::ZombieArchetypes;
+
+    #[test]
+    fn parse_shipped_archetypes() {
+        let mut scenes = vec![];
+        let archetypes = ZombieArchetypes::from_ron(
+            include_bytes!("../../assets/zombies/archetypes.zombies.ron"),
+            |scene| {
+                scenes.push(scene.to_string());
+                Handle::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(scenes.len(), archetypes.archetypes.len());
+        for archetype in &archetypes.archetypes {
+            assert!(
+                archetypes.get(&archetype.name).is_some(),
+                "{} can't be found by name",
+                archetype.name
+            );
+            assert!(archetype.count > 0, "{} spawns no zombie", archetype.name);
+        }
+        // nests need something to spawn from the first wave
+        assert!(archetypes
+            .archetypes
+            .iter()
+            .any(|archetype| archetype.from_wave <= 1 && archetype.weight > 0));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::Deserialize;
+
+use crate::assets::BalanceAssets;
+
+use super::{difficulty::Difficulty, effects::Effect, towers::TowerKind};
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(reload_balance);
+    }
+}
+
+/// Numbers driving the game, loaded from a `.balance.ron` file.
+///
+/// A copy is kept as a resource, refreshed when the file changes.
+#[derive(TypeUuid, Deserialize, Clone, Debug)]
+#[uuid = "0c0a4f6e-5d3b-4a36-9a47-2f1b6d0f83c1"]
+pub(crate) struct Balance {
+    pub(crate) towers: HashMap<TowerKind, TowerStats>,
+    pub(crate) upgrade: UpgradeStats,
+    /// Part of the credits spent on a tower given back when selling it.
+    pub(crate) refund: f32,
+    pub(crate) waves: WaveStats,
+    pub(crate) scenery: SceneryStats,
+    pub(crate) difficulties: HashMap<Difficulty, DifficultyStats>,
+}
+
+impl Balance {
+    pub(crate) fn tower(&self, kind: TowerKind) -> &TowerStats {
+        &self.towers[&kind]
+    }
+
+    pub(crate) fn difficulty(&self, difficulty: Difficulty) -> &DifficultyStats {
+        &self.difficulties[&difficulty]
+    }
+
+    /// Parse a balance file, checking that every tower and difficulty has its stats and that
+    /// chances and impacts are valid.
+    pub(crate) fn from_ron(bytes: &[u8]) -> Result<Self, bevy::asset::Error> {
+        let balance: Balance = ron::de::from_bytes(bytes)?;
+        if let Some(kind) = TowerKind::ALL
+            .iter()
+            .find(|kind| !balance.towers.contains_key(kind))
+        {
+            return Err(bevy::asset::Error::msg(format!(
+                "missing stats for tower {:?}",
+                kind
+            )));
+        }
+        if let Some((kind, error)) = TowerKind::ALL.iter().find_map(|kind| {
+            balance
+                .tower(*kind)
+                .impact
+                .error()
+                .map(|error| (kind, error))
+        }) {
+            return Err(bevy::asset::Error::msg(format!(
+                "invalid impact for tower {:?}: {}",
+                kind, error
+            )));
+        }
+        if let Some(difficulty) = Difficulty::ALL
+            .iter()
+            .find(|difficulty| !balance.difficulties.contains_key(difficulty))
+        {
+            return Err(bevy::asset::Error::msg(format!(
+                "missing difficulty {:?}",
+                difficulty
+            )));
+        }
+        let scenery = &balance.scenery;
+        if let Some((name, chance)) = [
+            ("tree", scenery.tree),
+            ("bench", scenery.bench),
+            ("rock", scenery.rock),
+        ]
+        .into_iter()
+        .find(|(_, chance)| !(0.0..=1.0).contains(chance))
+        {
+            return Err(bevy::asset::Error::msg(format!(
+                "chance of a {} should be between 0 and 1, got {}",
+                name, chance
+            )));
+        }
+        if scenery.nest_distance.is_nan() || scenery.nest_distance <= 0.0 {
+            return Err(bevy::asset::Error::msg(format!(
+                "nest distance should be positive, got {}",
+                scenery.nest_distance
+            )));
+        }
+        Ok(balance)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct TowerStats {
+    pub(crate) cost: u32,
+    pub(crate) range: f32,
+    /// Seconds between two shots.
+    pub(crate) reload: f32,
+    pub(crate) strength: f32,
+    pub(crate) missile_speed: f32,
+    pub(crate) health: f32,
+    /// Effect applied to every zombie hit, and its duration.
+    #[serde(default)]
+    pub(crate) effect: Option<(Effect, f32)>,
+    #[serde(default)]
+    pub(crate) impact: ImpactStats,
+}
+
+/// What the missiles of a tower do when they reach their target.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) enum ImpactStats {
+    Single,
+    /// Damage all zombies within `radius` of the target.
+    Splash {
+        radius: f32,
+    },
+    /// Jump to the closest zombie within `range`, `jumps` more times, keeping `falloff` of the
+    /// strength at each jump.
+    Chain {
+        jumps: u32,
+        range: f32,
+        falloff: f32,
+    },
+}
+
+impl Default for ImpactStats {
+    fn default() -> Self {
+        ImpactStats::Single
+    }
+}
+
+impl ImpactStats {
+    /// Description of what's wrong with these stats, if anything.
+    fn error(&self) -> Option<String> {
+        match *self {
+            ImpactStats::Single => None,
+            ImpactStats::Splash { radius } if radius.is_nan() || radius <= 0.0 => {
+                Some(format!("splash radius should be positive, got {}", radius))
+            }
+            ImpactStats::Chain { range, .. } if range.is_nan() || range <= 0.0 => {
+                Some(format!("chain range should be positive, got {}", range))
+            }
+            ImpactStats::Chain { falloff, .. } if !(0.0..=1.0).contains(&falloff) => Some(format!(
+                "chain falloff should be between 0 and 1, got {}",
+                falloff
+            )),
+            ImpactStats::Splash { .. } | ImpactStats::Chain { .. } => None,
+        }
+    }
+}
+
+/// Multipliers applied to a tower for each level.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct UpgradeStats {
+    pub(crate) strength: f32,
+    pub(crate) range: f32,
+    pub(crate) health: f32,
+    pub(crate) reload: f32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct WaveStats {
+    /// Seconds to build between two waves.
+    pub(crate) build_phase: f32,
+    /// Waves between each new nest taking part.
+    pub(crate) waves_per_nest: u32,
+    /// Times each nest spawns during the first wave, one more for each wave.
+    pub(crate) spawns_per_nest: u32,
+    /// Seconds between two spawns of a nest during the first wave.
+    pub(crate) spawn_interval: f32,
+    /// Seconds removed from the spawn interval for each wave.
+    pub(crate) spawn_interval_decrease: f32,
+    pub(crate) min_spawn_interval: f32,
+    /// Seconds after the last spawn of a wave before the next build phase starts, even if
+    /// zombies are left.
+    pub(crate) max_fight: f32,
+}
+
+impl WaveStats {
+    /// Number of nests taking part in a wave.
+    pub(crate) fn nest_count(&self, wave: u32) -> usize {
+        1 + (wave / self.waves_per_nest.max(1)) as usize
+    }
+
+    /// Number of times each nest spawns during a wave.
+    pub(crate) fn spawns_per_nest(&self, wave: u32) -> u32 {
+        self.spawns_per_nest + wave
+    }
+
+    /// Seconds between two spawns of a nest during a wave.
+    pub(crate) fn spawn_interval(&self, wave: u32) -> f32 {
+        (self.spawn_interval - self.spawn_interval_decrease * wave as f32)
+            .max(self.min_spawn_interval)
+    }
+}
+
+/// Chances for a lot to get something when a tile is generated.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct SceneryStats {
+    /// A nest appears with a chance of the squared distance to the crystal divided by this.
+    pub(crate) nest_distance: f64,
+    pub(crate) tree: f64,
+    pub(crate) bench: f64,
+    pub(crate) rock: f64,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct DifficultyStats {
+    /// Lives at the start of a run.
+    pub(crate) life: u32,
+    /// Credits at the start of a run.
+    pub(crate) credits: u32,
+    /// Multiplier of the life of zombies.
+    pub(crate) zombie_health: f32,
+    /// Multiplier of the speed bonus of zombies.
+    pub(crate) zombie_speed: f32,
+    /// Multiplier of the credits earned by killing a zombie.
+    pub(crate) reward: f32,
+}
+
+#[derive(Default)]
+pub(crate) struct BalanceLoader;
+
+impl AssetLoader for BalanceLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let balance = Balance::from_ron(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(balance));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["balance.ron"]
+    }
+}
+
+/// Copy the balance file to the resource when it's edited.
+fn reload_balance(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Balance>>,
+    balances: Res<Assets<Balance>>,
+    handles: Option<Res<BalanceAssets>>,
+) {
+    let handle = match handles {
+        Some(handles) => handles.balance.clone_weak(),
+        None => return,
+    };
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle: modified } = event {
+            if *modified == handle {
+                if let Some(balance) = balances.get(&handle) {
+                    info!("balance reloaded");
+                    commands.insert_resource(balance.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Balance;
+
+    #[test]
+    fn parse_shipped_balance() {
+        let balance = Balance::from_ron(include_bytes!("../../assets/game.balance.ron")).unwrap();
+        assert!(balance.refund >= 0.0 && balance.refund <= 1.0);
+    }
+
+    #[test]
+    fn reject_invalid_balance() {
+        let shipped = include_str!("../../assets/game.balance.ron");
+        let invalid = shipped.replace("tree: 0.01", "tree: 1.5");
+        assert_ne!(invalid, shipped);
+        assert!(Balance::from_ron(invalid.as_bytes()).is_err());
+    }
+}
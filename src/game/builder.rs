@@ -1,25 +1,32 @@
 use bevy::{
     pbr::NotShadowCaster,
     prelude::{
-        shape, AlphaMode, App, Assets, BuildChildren, Color, Commands, Component,
-        DespawnRecursiveExt, Entity, FromWorld, Handle, Input, Mesh, MouseButton, PbrBundle, Query,
-        Res, ResMut, StandardMaterial, SystemSet, Transform, Vec2, Vec3, With,
+        shape, AlphaMode, App, Assets, BuildChildren, Children, Color, Commands, Component,
+        DespawnRecursiveExt, Entity, EventReader, EventWriter, FromWorld, Handle, IVec2, Input,
+        Mesh, MouseButton, Or, PbrBundle, Query, Res, ResMut, StandardMaterial, SystemSet,
+        Transform, Vec2, Vec3, With,
     },
+    render::view::NoFrustumCulling,
     scene::SceneBundle,
-    time::Timer,
     utils::default,
     window::Windows,
 };
 
-use crate::{assets::BuildingAssets, game::terrain_spawner::map_to_world};
+use crate::{assets::BuildingAssets, game::terrain_spawner::map_to_world, GameState};
 
 use super::{
-    heightmap::LOW_DEF,
+    balance::Balance,
+    heightmap::TerrainConfig,
     nests::ZombieNest,
+    on_playing_update,
     stats::{GameTag, Stats},
     terra::Plane,
-    terrain_spawner::{CursorPosition, FilledLot, Map, Occupying, Pathfinding, TOWER_SCALE},
-    towers::Tower,
+    terrain_spawner::{
+        world_to_map, CursorPosition, FilledLot, Map, MeshCache, NavmeshChanged, Occupying,
+        Pathfinding, TOWER_SCALE,
+    },
+    towers::{InspectedTower, RangeRing, Tower, TowerDestroyed, TowerKind, TowerModel},
+    ui::in_ui_zone,
     PlayingState,
 };
 
@@ -27,16 +34,32 @@ pub(crate) struct Plugin;
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<CursorMaterials>()
+            .init_resource::<SelectedTower>()
+            .add_event::<SellTower>()
             .add_system_set(SystemSet::on_enter(PlayingState::Building).with_system(display_cursor))
             .add_system_set(SystemSet::on_exit(PlayingState::Building).with_system(clear))
             .add_system_set(
-                SystemSet::on_update(PlayingState::Building)
+                SystemSet::new()
+                    .with_run_criteria(on_playing_update(PlayingState::Building))
                     .with_system(update_cursor)
                     .with_system(build),
-            );
+            )
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(remove_towers));
+    }
+}
+
+/// Kind of tower to build, picked from the palette.
+pub(crate) struct SelectedTower(pub(crate) TowerKind);
+
+impl Default for SelectedTower {
+    fn default() -> Self {
+        SelectedTower(TowerKind::Basic)
     }
 }
 
+/// Sent to sell a tower, freeing its lot on both planes.
+pub(crate) struct SellTower(pub(crate) Entity);
+
 struct CursorMaterials {
     valid: Handle<StandardMaterial>,
     blocked: Handle<StandardMaterial>,
@@ -73,48 +96,88 @@ fn display_cursor(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     materials: Res<CursorMaterials>,
+    config: Res<TerrainConfig>,
+    ring: Res<RangeRing>,
+    selected: Res<SelectedTower>,
+    balance: Res<Balance>,
+    (cursor_position, plane, mesh_cache): (Res<CursorPosition>, Res<Plane>, Res<MeshCache>),
 ) {
+    let low_def = config.lots_per_tile;
     commands
         .spawn_bundle(PbrBundle {
             mesh: meshes.add(Mesh::from(shape::Box::new(
-                1.0 / LOW_DEF as f32,
+                1.0 / low_def as f32,
                 0.75,
-                1.0 / LOW_DEF as f32,
+                1.0 / low_def as f32,
             ))),
             material: materials.valid.clone_weak(),
             transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
             ..Default::default()
         })
         .insert_bundle((CursorSelection, NotShadowCaster));
+    let center = Vec2::new(cursor_position.world.x, cursor_position.world.z);
+    let range = RangeRing::mesh(center, balance.tower(selected.0).range, |point| {
+        mesh_cache.height_at(point, *plane, &meshes, &config)
+    });
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(range),
+            material: ring.material.clone_weak(),
+            ..default()
+        })
+        .insert_bundle((CursorRange, NotShadowCaster, NoFrustumCulling));
 }
 
 #[derive(Component)]
 struct CursorSelection;
 
-fn clear(mut commands: Commands, cursor: Query<Entity, With<CursorSelection>>) {
-    commands.entity(cursor.single()).despawn_recursive();
+#[derive(Component)]
+struct CursorRange;
+
+fn clear(
+    mut commands: Commands,
+    cursor: Query<Entity, Or<(With<CursorSelection>, With<CursorRange>)>>,
+) {
+    for entity in &cursor {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
 fn update_cursor(
     cursor_position: Res<CursorPosition>,
     mut cursor: Query<(&mut Transform, &mut Handle<StandardMaterial>), With<CursorSelection>>,
+    range: Query<&Handle<Mesh>, With<CursorRange>>,
     map: Res<Map>,
     plane: Res<Plane>,
     materials: Res<CursorMaterials>,
     stats: Res<Stats>,
+    selected: Res<SelectedTower>,
+    balance: Res<Balance>,
+    (config, mesh_cache, mut meshes): (Res<TerrainConfig>, Res<MeshCache>, ResMut<Assets<Mesh>>),
 ) {
     let (mut transform, mut material) = cursor.single_mut();
+    let tower_stats = balance.tower(selected.0);
+    let cost = tower_stats.cost;
     transform.translation = cursor_position.world;
-    if map
-        .lots
-        .get(&(cursor_position.map, *plane))
-        .and_then(|lot| lot.get(&cursor_position.lot))
-        .map(|o| o.is_free())
-        .unwrap_or(true)
+    if cursor_position.is_changed()
+        || selected.is_changed()
+        || balance.is_changed()
+        || plane.is_changed()
     {
-        if *material != materials.valid && stats.credits >= 10 {
+        let center = Vec2::new(cursor_position.world.x, cursor_position.world.z);
+        let mesh = RangeRing::mesh(center, tower_stats.range, |point| {
+            mesh_cache.height_at(point, *plane, &meshes, &config)
+        });
+        for handle in &range {
+            if let Some(ring) = meshes.get_mut(handle) {
+                *ring = mesh.clone();
+            }
+        }
+    }
+    if map.is_buildable((cursor_position.map, cursor_position.lot)) {
+        if *material != materials.valid && stats.credits >= cost {
             *material = materials.valid.clone_weak();
-        } else if *material != materials.lacking_resources && stats.credits < 10 {
+        } else if *material != materials.lacking_resources && stats.credits < cost {
             *material = materials.lacking_resources.clone_weak();
         }
     } else if *material == materials.valid {
@@ -133,14 +196,18 @@ fn build(
     building_assets: Res<BuildingAssets>,
     cursor: Query<&Handle<StandardMaterial>, With<CursorSelection>>,
     materials: Res<CursorMaterials>,
-    pathfinding: Res<Pathfinding>,
+    mut pathfinding: ResMut<Pathfinding>,
     nests: Query<&ZombieNest>,
     mut stats: ResMut<Stats>,
+    config: Res<TerrainConfig>,
+    mut navmesh_changes: EventWriter<NavmeshChanged>,
+    (selected, balance): (Res<SelectedTower>, Res<Balance>),
 ) {
+    let kind = selected.0;
     if mouse_button_input.just_released(MouseButton::Left) {
-        if let Some(pos) = windows.primary().cursor_position() {
-            if pos.x < 140.0 && pos.y > 460.0 {
-                // in UI zone
+        let window = windows.primary();
+        if let Some(pos) = window.cursor_position() {
+            if in_ui_zone(pos, window) {
                 return;
             }
         } else {
@@ -149,57 +216,168 @@ fn build(
         }
 
         if *cursor.single() == materials.valid {
-            let mut temp_mesh = pathfinding.clone();
-            temp_mesh.cut_polygon_out((cursor_position.map, cursor_position.lot));
-            for nest in nests.iter() {
-                let position = map_to_world((nest.map, nest.lot));
-                if !temp_mesh.mesh.path(position, Vec2::ZERO).complete {
-                    return;
-                }
+            let coords = (cursor_position.map, cursor_position.lot);
+            if blocks_a_nest(coords, &pathfinding, nests.iter(), &config) {
+                return;
+            }
+            place_tower(
+                &mut commands,
+                kind,
+                *plane,
+                coords,
+                &lots,
+                &building_assets,
+                &mut map,
+                &mut pathfinding,
+                &mut stats,
+                &config,
+                &balance,
+                &mut navmesh_changes,
+            );
+        }
+    }
+}
+
+/// Whether blocking the lot at `coords` would leave a nest without a path to the crystal.
+pub(crate) fn blocks_a_nest<'a>(
+    coords: (IVec2, IVec2),
+    pathfinding: &Pathfinding,
+    mut nests: impl Iterator<Item = &'a ZombieNest>,
+    config: &TerrainConfig,
+) -> bool {
+    let mut temp_mesh = pathfinding.clone();
+    temp_mesh.cut_polygon_out(coords, config);
+    nests.any(|nest| !temp_mesh.reaches_crystal(map_to_world((nest.map, nest.lot), config)))
+}
+
+/// Build a tower of `kind` on the lot at `coords`, blocking it on both planes and paying for it.
+pub(crate) fn place_tower(
+    commands: &mut Commands,
+    kind: TowerKind,
+    plane: Plane,
+    coords: (IVec2, IVec2),
+    lots: &Query<(Entity, &FilledLot)>,
+    building_assets: &BuildingAssets,
+    map: &mut Map,
+    pathfinding: &mut Pathfinding,
+    stats: &mut Stats,
+    config: &TerrainConfig,
+    balance: &Balance,
+    navmesh_changes: &mut EventWriter<NavmeshChanged>,
+) {
+    map.lots
+        .get_mut(&(coords.0, plane))
+        .unwrap()
+        .insert(coords.1, Occupying::Tower(kind));
+    let other_plane = map.lots.get_mut(&(coords.0, plane.next())).unwrap();
+    // the lot may be taken on the other plane, as the terrain is different there
+    let previous = other_plane.remove(&coords.1);
+    match previous {
+        Some(occupying) if !occupying.is_free() => {
+            other_plane.insert(coords.1, occupying);
+        }
+        previous => {
+            other_plane.insert(coords.1, Occupying::Block(previous.map(Box::new)));
+        }
+    }
+    navmesh_changes.send_batch(pathfinding.refresh_lot(coords, map, config).into_iter());
+    let low_def = config.lots_per_tile;
+    for (entity, lot) in lots {
+        if lot.x == coords.0.x && lot.z == coords.0.y {
+            commands.entity(entity).add_children(|lot| {
+                lot.spawn_bundle(SceneBundle {
+                    scene: kind.scene(plane, building_assets),
+                    transform: Transform {
+                        scale: kind.scale() * TOWER_SCALE / low_def as f32,
+                        translation: Vec3::new(
+                            -(coords.1.x - low_def as i32 / 2) as f32 / low_def as f32,
+                            0.03,
+                            (coords.1.y - low_def as i32 / 2) as f32 / low_def as f32,
+                        ),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(TowerModel { lot: coords.1 });
+            })
+        }
+    }
+    let world = map_to_world(coords, config);
+    commands.spawn_bundle((
+        Tower::new(kind, plane, balance),
+        Transform::from_xyz(world.x, 0.05, world.y),
+        GameTag,
+    ));
+    stats.credits -= balance.tower(kind).cost;
+}
+
+/// Remove sold and destroyed towers, freeing their lot and giving back to the other plane what
+/// was there before.
+fn remove_towers(
+    mut commands: Commands,
+    mut sells: EventReader<SellTower>,
+    mut destroyed: EventReader<TowerDestroyed>,
+    towers: Query<(&Tower, &Transform)>,
+    lots: Query<(&FilledLot, &Children)>,
+    models: Query<&TowerModel>,
+    mut map: ResMut<Map>,
+    mut pathfinding: ResMut<Pathfinding>,
+    mut stats: ResMut<Stats>,
+    mut inspected: ResMut<InspectedTower>,
+    config: Res<TerrainConfig>,
+    mut navmesh_changes: EventWriter<NavmeshChanged>,
+    balance: Res<Balance>,
+) {
+    let mut removed = vec![];
+    let sold = sells.iter().map(|SellTower(entity)| (*entity, true));
+    let destroyed = destroyed
+        .iter()
+        .map(|TowerDestroyed(entity)| (*entity, false));
+    for (entity, refund) in sold.chain(destroyed) {
+        if removed.contains(&entity) {
+            continue;
+        }
+        removed.push(entity);
+        let (tower, transform) = match towers.get(entity) {
+            Ok(tower) => tower,
+            Err(_) => continue,
+        };
+        let coords = world_to_map(
+            Vec2::new(transform.translation.x, transform.translation.z),
+            &config,
+        );
+        if let Some(lots) = map.lots.get_mut(&(coords.0, tower.plane)) {
+            lots.remove(&coords.1);
+        }
+        if let Some(lots) = map.lots.get_mut(&(coords.0, tower.plane.next())) {
+            if let Some(Occupying::Block(previous)) = lots.get_mut(&coords.1) {
+                match previous.take() {
+                    Some(previous) => lots.insert(coords.1, *previous),
+                    None => lots.remove(&coords.1),
+                };
+            }
+        }
+        navmesh_changes.send_batch(pathfinding.refresh_lot(coords, &map, &config).into_iter());
+        for (lot, children) in &lots {
+            if lot.x != coords.0.x || lot.z != coords.0.y {
+                continue;
             }
-            map.lots
-                .get_mut(&(cursor_position.map, *plane))
-                .unwrap()
-                .insert(cursor_position.lot, Occupying::Tower);
-            map.lots
-                .get_mut(&(cursor_position.map, plane.next()))
-                .unwrap()
-                .insert(cursor_position.lot, Occupying::Block);
-            for (entity, lot) in &lots {
-                if lot.x == cursor_position.map.x && lot.z == cursor_position.map.y {
-                    commands.entity(entity).add_children(|lot| {
-                        lot.spawn_bundle(SceneBundle {
-                            scene: if *plane == Plane::Material {
-                                building_assets.material_tower.clone_weak()
-                            } else {
-                                building_assets.ethereal_tower.clone_weak()
-                            },
-                            transform: Transform {
-                                scale: Vec3::splat(TOWER_SCALE / LOW_DEF as f32),
-                                translation: Vec3::new(
-                                    -(cursor_position.lot.x - LOW_DEF as i32 / 2) as f32
-                                        / LOW_DEF as f32,
-                                    0.03,
-                                    (cursor_position.lot.y - LOW_DEF as i32 / 2) as f32
-                                        / LOW_DEF as f32,
-                                ),
-                                ..default()
-                            },
-                            ..default()
-                        });
-                    })
+            for child in children.iter() {
+                if models
+                    .get(*child)
+                    .map(|model| model.lot == coords.1)
+                    .unwrap_or(false)
+                {
+                    commands.entity(*child).despawn_recursive();
                 }
             }
-            commands.spawn_bundle((
-                Tower {
-                    timer: Timer::from_seconds(1.0, true),
-                    strength: 1.0,
-                    plane: *plane,
-                },
-                Transform::from_translation(cursor_position.world),
-                GameTag,
-            ));
-            stats.credits -= 10;
+        }
+        if refund {
+            stats.credits += tower.refund(&balance);
+        }
+        commands.entity(entity).despawn();
+        if inspected.0 == Some(entity) {
+            inspected.0 = None;
         }
     }
 }
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// Difficulty of a run, picked in the menu and recorded with the score.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Nightmare,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::Normal
+    }
+}
+
+impl Difficulty {
+    pub(crate) const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Nightmare,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Nightmare => "nightmare",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|difficulty| difficulty.name() == name)
+    }
+
+    /// Harder difficulty, staying on the hardest one.
+    pub(crate) fn harder(self) -> Self {
+        let index = Self::ALL.iter().position(|d| *d == self).unwrap();
+        Self::ALL[(index + 1).min(Self::ALL.len() - 1)]
+    }
+
+    /// Easier difficulty, staying on the easiest one.
+    pub(crate) fn easier(self) -> Self {
+        let index = Self::ALL.iter().position(|d| *d == self).unwrap();
+        Self::ALL[index.saturating_sub(1)]
+    }
+}
@@ -0,0 +1,301 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::GameState;
+
+use super::{
+    health_bars::HealthBar,
+    timestep::{playing, SimulationStage, SimulationTime},
+    towers::{hurt, Tower},
+    zombies::{IdleZombie, Zombie},
+    PlayingState,
+};
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TintedMaterials>()
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(playing)
+                    .with_system(tick_effects),
+            )
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(tint_zombies));
+    }
+}
+
+/// Something temporarily changing a zombie.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub(crate) enum Effect {
+    /// Multiplies the speed.
+    Slow(f32),
+    /// Damage per second.
+    Burn(f32),
+    /// Can't move at all.
+    Stun,
+    /// Multiplies the damage taken.
+    ArmorBreak(f32),
+    /// Pinned between planes, towers from both planes can reach it.
+    PlaneLock,
+}
+
+/// Burns stacking on the same zombie.
+const MAX_BURNS: usize = 3;
+
+impl Effect {
+    /// Effects of the same kind follow the same stacking rules.
+    fn same_kind(&self, other: &Effect) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    fn tint(&self) -> Color {
+        match self {
+            Effect::Slow(_) => Color::rgb(0.5, 0.7, 1.0),
+            Effect::Burn(_) => Color::rgb(1.0, 0.5, 0.2),
+            Effect::Stun => Color::rgb(1.0, 1.0, 0.4),
+            Effect::ArmorBreak(_) => Color::rgb(0.8, 0.3, 0.3),
+            Effect::PlaneLock => Color::rgb(0.8, 0.4, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ActiveEffect {
+    effect: Effect,
+    remaining: f32,
+    /// Tower that applied it, credited for burn damage.
+    source: Option<Entity>,
+}
+
+/// Effects currently applied to a zombie.
+#[derive(Component, Default, Clone, Debug)]
+pub(crate) struct StatusEffects {
+    active: Vec<ActiveEffect>,
+    /// Tint currently displayed on the zombie scene.
+    shown_tint: Option<Color>,
+}
+
+impl StatusEffects {
+    /// Apply an effect for `duration` seconds.
+    ///
+    /// Burns stack up to [`MAX_BURNS`]. For other effects, only the strongest is kept and
+    /// its duration is refreshed.
+    pub(crate) fn apply(&mut self, effect: Effect, duration: f32, source: Option<Entity>) {
+        let new = ActiveEffect {
+            effect,
+            remaining: duration,
+            source,
+        };
+        if let Effect::Burn(_) = effect {
+            let burns = self
+                .active
+                .iter()
+                .filter(|active| active.effect.same_kind(&effect))
+                .count();
+            if burns < MAX_BURNS {
+                self.active.push(new);
+            } else if let Some(shortest) = self
+                .active
+                .iter_mut()
+                .filter(|active| active.effect.same_kind(&effect))
+                .min_by(|a, b| a.remaining.total_cmp(&b.remaining))
+            {
+                *shortest = new;
+            }
+            return;
+        }
+        match self
+            .active
+            .iter_mut()
+            .find(|active| active.effect.same_kind(&effect))
+        {
+            Some(active) => {
+                let stronger = match (active.effect, effect) {
+                    (Effect::Slow(current), Effect::Slow(new)) => new < current,
+                    (Effect::ArmorBreak(current), Effect::ArmorBreak(new)) => new > current,
+                    _ => false,
+                };
+                if stronger {
+                    active.effect = effect;
+                    active.source = source;
+                }
+                active.remaining = active.remaining.max(duration);
+            }
+            None => self.active.push(new),
+        }
+    }
+
+    /// Multiplier applied to the speed.
+    pub(crate) fn speed_factor(&self) -> f32 {
+        self.active
+            .iter()
+            .map(|active| match active.effect {
+                Effect::Stun => 0.0,
+                Effect::Slow(factor) => factor,
+                _ => 1.0,
+            })
+            .fold(1.0, f32::min)
+    }
+
+    /// Multiplier applied to the damage taken.
+    pub(crate) fn damage_factor(&self) -> f32 {
+        self.active
+            .iter()
+            .map(|active| match active.effect {
+                Effect::ArmorBreak(factor) => factor,
+                _ => 1.0,
+            })
+            .fold(1.0, f32::max)
+    }
+
+    pub(crate) fn plane_locked(&self) -> bool {
+        self.active
+            .iter()
+            .any(|active| active.effect == Effect::PlaneLock)
+    }
+
+    /// Tint of the most recent effect.
+    fn tint(&self) -> Option<Color> {
+        self.active.last().map(|active| active.effect.tint())
+    }
+}
+
+/// Expire effects, and apply burn damage.
+pub(crate) fn tick_effects(
+    mut zombies: Query<(
+        &mut StatusEffects,
+        Option<&mut Zombie>,
+        Option<&mut IdleZombie>,
+    )>,
+    mut towers: Query<&mut Tower>,
+    time: Res<SimulationTime>,
+    playing_state: Res<State<PlayingState>>,
+) {
+    if *playing_state.current() == PlayingState::SwitchingPlane {
+        return;
+    }
+    let delta = time.delta_seconds();
+    for (mut effects, mut zombie, mut idle) in &mut zombies {
+        let damage_factor = effects.damage_factor();
+        for active in &effects.active {
+            if let Effect::Burn(per_second) = active.effect {
+                let damage = per_second * delta * damage_factor;
+                let tower = active.source.and_then(|source| towers.get_mut(source).ok());
+                if let Some(zombie) = zombie.as_deref_mut() {
+                    hurt(&mut zombie.life, damage, tower);
+                } else if let Some(idle) = idle.as_deref_mut() {
+                    hurt(&mut idle.life, damage, tower);
+                }
+            }
+        }
+        for active in &mut effects.active {
+            active.remaining -= delta;
+        }
+        effects.active.retain(|active| active.remaining > 0.0);
+    }
+}
+
+/// Tinted copies of the zombie materials.
+#[derive(Default)]
+struct TintedMaterials(HashMap<(Handle<StandardMaterial>, [u8; 3]), Handle<StandardMaterial>>);
+
+/// Material of a zombie scene mesh before it was tinted.
+#[derive(Component)]
+struct OriginalMaterial(Handle<StandardMaterial>);
+
+fn tint_zombies(
+    mut commands: Commands,
+    mut zombies: Query<(Entity, &mut StatusEffects)>,
+    children: Query<&Children>,
+    mut meshes: Query<
+        (&mut Handle<StandardMaterial>, Option<&OriginalMaterial>),
+        Without<HealthBar>,
+    >,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut tinted: ResMut<TintedMaterials>,
+) {
+    for (entity, mut effects) in &mut zombies {
+        let tint = effects.tint();
+        if tint == effects.shown_tint {
+            continue;
+        }
+        // the scene may not be spawned yet, try again next frame
+        if children.get(entity).is_err() {
+            continue;
+        }
+        effects.shown_tint = tint;
+
+        let mut to_visit = vec![entity];
+        while let Some(current) = to_visit.pop() {
+            if let Ok(current_children) = children.get(current) {
+                to_visit.extend(current_children.iter());
+            }
+            let (mut material, original) = match meshes.get_mut(current) {
+                Ok(mesh) => mesh,
+                Err(_) => continue,
+            };
+            let original = match original {
+                Some(original) => original.0.clone(),
+                None => {
+                    commands
+                        .entity(current)
+                        .insert(OriginalMaterial(material.clone()));
+                    material.clone()
+                }
+            };
+            *material = match tint {
+                None => original,
+                Some(tint) => {
+                    let key = (
+                        original.clone(),
+                        [tint.r(), tint.g(), tint.b()].map(|c| (c * 255.0) as u8),
+                    );
+                    tinted
+                        .0
+                        .entry(key)
+                        .or_insert_with(|| {
+                            let mut copy = materials.get(&original).cloned().unwrap_or_default();
+                            copy.base_color = Color::rgba(
+                                copy.base_color.r() * tint.r(),
+                                copy.base_color.g() * tint.g(),
+                                copy.base_color.b() * tint.b(),
+                                copy.base_color.a(),
+                            );
+                            materials.add(copy)
+                        })
+                        .clone()
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Effect, StatusEffects, MAX_BURNS};
+
+    #[test]
+    fn stacking() {
+        let mut effects = StatusEffects::default();
+        effects.apply(Effect::Slow(0.5), 1.0, None);
+        effects.apply(Effect::Slow(0.8), 3.0, None);
+        assert_eq!(effects.speed_factor(), 0.5);
+        assert_eq!(effects.active[0].remaining, 3.0);
+
+        for _ in 0..MAX_BURNS + 2 {
+            effects.apply(Effect::Burn(1.0), 1.0, None);
+        }
+        let burns = effects
+            .active
+            .iter()
+            .filter(|active| matches!(active.effect, Effect::Burn(_)))
+            .count();
+        assert_eq!(burns, MAX_BURNS);
+
+        effects.apply(Effect::Stun, 0.5, None);
+        assert_eq!(effects.speed_factor(), 0.0);
+        assert!(!effects.plane_locked());
+    }
+}
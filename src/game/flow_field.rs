@@ -0,0 +1,278 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    heightmap::TerrainConfig,
+    terra::Plane,
+    terrain_spawner::{map_to_world, world_to_map, Map, NavmeshChanged},
+};
+
+/// How zombies find their way to the crystal.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub(crate) enum PathfindingMode {
+    /// Each zombie asks the navigation mesh for a path.
+    NavMesh,
+    /// Zombies follow a distance field shared by every zombie of a plane.
+    FlowField,
+}
+
+impl Default for PathfindingMode {
+    fn default() -> Self {
+        Self::NavMesh
+    }
+}
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+const NEIGHBOURS: [(IVec2, u32); 8] = [
+    (IVec2::new(1, 0), STRAIGHT_COST),
+    (IVec2::new(-1, 0), STRAIGHT_COST),
+    (IVec2::new(0, 1), STRAIGHT_COST),
+    (IVec2::new(0, -1), STRAIGHT_COST),
+    (IVec2::new(1, 1), DIAGONAL_COST),
+    (IVec2::new(1, -1), DIAGONAL_COST),
+    (IVec2::new(-1, 1), DIAGONAL_COST),
+    (IVec2::new(-1, -1), DIAGONAL_COST),
+];
+
+/// Flow fields for each plane.
+#[derive(Default)]
+pub(crate) struct FlowFields {
+    material: FlowField,
+    ethereal: FlowField,
+}
+
+impl FlowFields {
+    pub(crate) fn plane(&self, plane: Plane) -> &FlowField {
+        match plane {
+            Plane::Material => &self.material,
+            Plane::Ethereal => &self.ethereal,
+        }
+    }
+
+    fn plane_mut(&mut self, plane: Plane) -> &mut FlowField {
+        match plane {
+            Plane::Material => &mut self.material,
+            Plane::Ethereal => &mut self.ethereal,
+        }
+    }
+}
+
+/// Distance to the crystal from every reachable lot of a plane.
+#[derive(Default)]
+pub(crate) struct FlowField {
+    distances: HashMap<IVec2, u32>,
+}
+
+impl FlowField {
+    pub(crate) fn from_map(map: &Map, plane: Plane, config: &TerrainConfig) -> Self {
+        let walkable = |cell: IVec2| {
+            let (tile, lot) = from_cell(cell, config);
+            map.lots
+                .get(&(tile, plane))
+                .map(|lots| {
+                    lots.get(&lot)
+                        .map_or(true, |occupying| occupying.is_path_free())
+                })
+                .unwrap_or(false)
+        };
+
+        // don't cut corners around obstacles
+        let corners_walkable = |cell: IVec2, offset: IVec2| {
+            walkable(cell + IVec2::new(offset.x, 0)) && walkable(cell + IVec2::new(0, offset.y))
+        };
+
+        let mut distances = HashMap::default();
+        let goal = crystal_cell(config);
+        if !walkable(goal) {
+            return Self { distances };
+        }
+        let mut queue = BinaryHeap::new();
+        distances.insert(goal, 0);
+        queue.push(Reverse((0, goal.x, goal.y)));
+        while let Some(Reverse((distance, x, y))) = queue.pop() {
+            let cell = IVec2::new(x, y);
+            if distances
+                .get(&cell)
+                .map_or(false, |known| *known < distance)
+            {
+                continue;
+            }
+            for (offset, cost) in NEIGHBOURS {
+                let next = cell + offset;
+                if !walkable(next) || (is_diagonal(offset) && !corners_walkable(cell, offset)) {
+                    continue;
+                }
+                let next_distance = distance + cost;
+                if distances
+                    .get(&next)
+                    .map_or(true, |known| next_distance < *known)
+                {
+                    distances.insert(next, next_distance);
+                    queue.push(Reverse((next_distance, next.x, next.y)));
+                }
+            }
+        }
+        Self { distances }
+    }
+
+    /// Distance left to walk from `position` to the crystal.
+    pub(crate) fn distance(&self, position: Vec2, config: &TerrainConfig) -> Option<f32> {
+        let cell = to_cell(world_to_map(position, config), config);
+        self.distances
+            .get(&cell)
+            .map(|distance| *distance as f32 / STRAIGHT_COST as f32 / config.lots_per_tile as f32)
+    }
+
+    /// Next point to walk to from `position` to get closer to the crystal.
+    pub(crate) fn next_waypoint(&self, position: Vec2, config: &TerrainConfig) -> Option<Vec2> {
+        let cell = to_cell(world_to_map(position, config), config);
+        let current = self.distances.get(&cell).copied();
+        if current == Some(0) {
+            return Some(Vec2::ZERO);
+        }
+        NEIGHBOURS
+            .iter()
+            .filter(|(offset, _)| {
+                !is_diagonal(*offset)
+                    || [IVec2::new(offset.x, 0), IVec2::new(0, offset.y)]
+                        .iter()
+                        .all(|side| self.distances.contains_key(&(cell + *side)))
+            })
+            .filter_map(|(offset, _)| {
+                self.distances
+                    .get(&(cell + *offset))
+                    .map(|distance| (*distance, cell + *offset))
+            })
+            .filter(|(distance, _)| current.map_or(true, |current| *distance < current))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(distance, next)| {
+                if distance == 0 {
+                    Vec2::ZERO
+                } else {
+                    map_to_world(from_cell(next, config), config)
+                }
+            })
+    }
+}
+
+fn is_diagonal(offset: IVec2) -> bool {
+    offset.x != 0 && offset.y != 0
+}
+
+/// Lot coordinates on a single grid spanning every tile.
+pub(crate) fn to_cell((tile, lot): (IVec2, IVec2), config: &TerrainConfig) -> IVec2 {
+    let low_def = config.lots_per_tile as i32;
+    IVec2::new(tile.x * low_def - lot.x, tile.y * low_def + lot.y)
+}
+
+pub(crate) fn from_cell(cell: IVec2, config: &TerrainConfig) -> (IVec2, IVec2) {
+    let low_def = config.lots_per_tile as i32;
+    let tile = IVec2::new(
+        (cell.x + low_def - 1).div_euclid(low_def),
+        cell.y.div_euclid(low_def),
+    );
+    (
+        tile,
+        IVec2::new(tile.x * low_def - cell.x, cell.y.rem_euclid(low_def)),
+    )
+}
+
+fn crystal_cell(config: &TerrainConfig) -> IVec2 {
+    let low_def = config.lots_per_tile as i32;
+    to_cell((IVec2::ZERO, IVec2::new(low_def / 2, low_def / 2)), config)
+}
+
+pub(crate) fn update_flow_fields(
+    mode: Res<PathfindingMode>,
+    mut flow_fields: ResMut<FlowFields>,
+    map: Res<Map>,
+    config: Res<TerrainConfig>,
+    mut navmesh_changes: EventReader<NavmeshChanged>,
+) {
+    if *mode != PathfindingMode::FlowField {
+        return;
+    }
+    let mut changed = vec![];
+    for change in navmesh_changes.iter() {
+        let plane = match change {
+            NavmeshChanged::Lots(plane, _) | NavmeshChanged::Rebuilt(plane) => *plane,
+        };
+        if !changed.contains(&plane) {
+            changed.push(plane);
+        }
+    }
+    for plane in changed {
+        *flow_fields.plane_mut(plane) = FlowField::from_map(&map, plane, &config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{IVec2, Vec2};
+    use bevy::utils::HashMap;
+
+    use crate::game::heightmap::TerrainConfig;
+    use crate::game::terra::Plane;
+    use crate::game::terrain_spawner::{map_to_world, Map, Occupying};
+
+    use super::FlowField;
+
+    #[test]
+    fn cell_round_trip() {
+        let config = TerrainConfig::default();
+        for x in -2..=2 {
+            for y in -2..=2 {
+                for lot_x in 0..5 {
+                    for lot_y in 0..5 {
+                        let coords = (IVec2::new(x, y), IVec2::new(lot_x, lot_y));
+                        assert_eq!(
+                            super::from_cell(super::to_cell(coords, &config), &config),
+                            coords
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn walk_around_obstacles() {
+        let config = TerrainConfig::default();
+        let mut map = Map::default();
+        let mut lots = HashMap::new();
+        for y in 0..5 {
+            lots.insert(IVec2::new(1, y), Occupying::Mountain);
+        }
+        lots.remove(&IVec2::new(1, 4));
+        map.lots.insert((IVec2::new(0, 0), Plane::Material), lots);
+        let field = FlowField::from_map(&map, Plane::Material, &config);
+
+        let mut position = map_to_world((IVec2::new(0, 0), IVec2::new(0, 0)), &config);
+        for _ in 0..20 {
+            match field.next_waypoint(position, &config) {
+                Some(Vec2::ZERO) => return,
+                Some(next) => position = next,
+                None => panic!("no path from {:?}", position),
+            }
+        }
+        panic!("didn't reach the crystal");
+    }
+
+    #[test]
+    fn distance_to_crystal() {
+        let config = TerrainConfig::default();
+        let mut map = Map::default();
+        map.lots
+            .insert((IVec2::new(0, 0), Plane::Material), HashMap::new());
+        let field = FlowField::from_map(&map, Plane::Material, &config);
+
+        assert_eq!(field.distance(Vec2::ZERO, &config), Some(0.0));
+        let two_lots_away = map_to_world((IVec2::new(0, 0), IVec2::new(2, 0)), &config);
+        let distance = field.distance(two_lots_away, &config).unwrap();
+        assert!((distance - 2.0 / config.lots_per_tile as f32).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,291 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+
+use crate::{assets::UiAssets, GameState};
+
+use super::{
+    stats::GameTag,
+    terra::Plane,
+    towers::ZombieHit,
+    zombies::{IdleZombie, Zombie, ZombieKind},
+};
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HealthBarAssets>()
+            .init_resource::<DamageNumbers>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn_health_bars)
+                    .with_system(update_health_bars)
+                    .with_system(toggle_damage_numbers)
+                    .with_system(spawn_damage_numbers)
+                    .with_system(float_damage_numbers),
+            );
+    }
+}
+
+const BAR_WIDTH: f32 = 0.12;
+const BAR_THICKNESS: f32 = 0.015;
+/// Height of the bar above the zombie.
+const BAR_OFFSET: f32 = 0.25;
+/// Seconds a damage number stays on screen.
+const DAMAGE_NUMBER_DURATION: f32 = 0.8;
+
+/// Whether to show floating damage numbers when a missile hits, toggled with `N`.
+pub(crate) struct DamageNumbers(pub(crate) bool);
+
+impl Default for DamageNumbers {
+    fn default() -> Self {
+        DamageNumbers(true)
+    }
+}
+
+struct HealthBarAssets {
+    mesh: Handle<Mesh>,
+    background: Handle<StandardMaterial>,
+    healthy: Handle<StandardMaterial>,
+    hurt: Handle<StandardMaterial>,
+    dying: Handle<StandardMaterial>,
+}
+
+impl FromWorld for HealthBarAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Mesh::from(shape::Quad::new(Vec2::new(
+                BAR_WIDTH,
+                BAR_THICKNESS,
+            ))));
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut unlit = |color: Color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                unlit: true,
+                ..default()
+            })
+        };
+        HealthBarAssets {
+            mesh,
+            background: unlit(Color::rgb(0.1, 0.1, 0.1)),
+            healthy: unlit(Color::rgb(0.2, 0.9, 0.2)),
+            hurt: unlit(Color::rgb(0.9, 0.8, 0.2)),
+            dying: unlit(Color::rgb(0.9, 0.2, 0.2)),
+        }
+    }
+}
+
+/// Billboard above a zombie, showing its remaining life. Being a child of the zombie, it's
+/// hidden with it when on the other plane.
+#[derive(Component)]
+pub(crate) struct HealthBar;
+
+/// Part of the health bar that shrinks with the life of the zombie.
+#[derive(Component)]
+struct HealthBarFill;
+
+fn spawn_health_bars(
+    mut commands: Commands,
+    zombies: Query<Entity, Added<ZombieKind>>,
+    assets: Res<HealthBarAssets>,
+) {
+    for zombie in &zombies {
+        commands.entity(zombie).with_children(|zombie| {
+            zombie
+                .spawn_bundle(SpatialBundle {
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(HealthBar)
+                .with_children(|bar| {
+                    bar.spawn_bundle(PbrBundle {
+                        mesh: assets.mesh.clone_weak(),
+                        material: assets.background.clone_weak(),
+                        ..default()
+                    })
+                    .insert_bundle((HealthBar, NotShadowCaster));
+                    bar.spawn_bundle(PbrBundle {
+                        mesh: assets.mesh.clone_weak(),
+                        material: assets.healthy.clone_weak(),
+                        transform: Transform::from_xyz(0.0, 0.0, 0.001),
+                        ..default()
+                    })
+                    .insert_bundle((
+                        HealthBar,
+                        HealthBarFill,
+                        NotShadowCaster,
+                    ));
+                });
+        });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_health_bars(
+    zombies: Query<(
+        &Transform,
+        &ZombieKind,
+        Option<&Zombie>,
+        Option<&IdleZombie>,
+        &Children,
+    )>,
+    mut bars: Query<
+        (&mut Transform, &mut Visibility, &Children),
+        (With<HealthBar>, Without<HealthBarFill>, Without<ZombieKind>),
+    >,
+    mut fills: Query<
+        (&mut Transform, &mut Handle<StandardMaterial>),
+        (With<HealthBarFill>, Without<ZombieKind>),
+    >,
+    camera: Query<
+        &Transform,
+        (
+            With<Camera>,
+            Without<HealthBar>,
+            Without<HealthBarFill>,
+            Without<ZombieKind>,
+        ),
+    >,
+    assets: Res<HealthBarAssets>,
+) {
+    let camera = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    for (transform, kind, zombie, idle, children) in &zombies {
+        let life = zombie
+            .map(|zombie| zombie.life)
+            .or_else(|| idle.map(|idle| idle.life))
+            .unwrap_or(0.0);
+        let ratio = (life / kind.max_life).clamp(0.0, 1.0);
+        let scale = transform.scale.x;
+        for child in children.iter() {
+            let (mut bar, mut visibility, parts) = match bars.get_mut(*child) {
+                Ok(bar) => bar,
+                Err(_) => continue,
+            };
+            // full bars would only clutter the screen
+            let damaged = ratio < 1.0;
+            if visibility.is_visible != damaged {
+                visibility.is_visible = damaged;
+            }
+            if !damaged {
+                continue;
+            }
+            // undo the rotation and scale of the zombie so the bar faces the camera
+            bar.translation = Vec3::Y * BAR_OFFSET / scale;
+            bar.rotation = transform.rotation.inverse() * camera.rotation;
+            bar.scale = Vec3::splat(1.0 / scale);
+            for part in parts.iter() {
+                if let Ok((mut fill, mut material)) = fills.get_mut(*part) {
+                    fill.scale.x = ratio.max(0.001);
+                    fill.translation.x = -(1.0 - ratio) * BAR_WIDTH / 2.0;
+                    let wanted = if ratio > 0.6 {
+                        &assets.healthy
+                    } else if ratio > 0.3 {
+                        &assets.hurt
+                    } else {
+                        &assets.dying
+                    };
+                    if *material != *wanted {
+                        *material = wanted.clone_weak();
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn toggle_damage_numbers(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut damage_numbers: ResMut<DamageNumbers>,
+) {
+    if keyboard_input.just_pressed(KeyCode::N) {
+        damage_numbers.0 = !damage_numbers.0;
+    }
+}
+
+/// Damage done by a missile, floating up from where it hit.
+#[derive(Component)]
+struct DamageNumber {
+    position: Vec3,
+    timer: Timer,
+}
+
+fn spawn_damage_numbers(
+    mut commands: Commands,
+    mut hits: EventReader<ZombieHit>,
+    damage_numbers: Res<DamageNumbers>,
+    plane: Res<Plane>,
+    ui_handles: Res<UiAssets>,
+) {
+    for hit in hits.iter() {
+        if !damage_numbers.0 || hit.plane != *plane {
+            continue;
+        }
+        commands
+            .spawn_bundle(TextBundle {
+                text: Text::from_section(
+                    format!("{:.0}", hit.damage.max(1.0)),
+                    TextStyle {
+                        font: ui_handles.font_sub.clone_weak(),
+                        color: Color::rgb(1.0, 0.9, 0.6),
+                        font_size: 18.,
+                    },
+                ),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert_bundle((
+                DamageNumber {
+                    position: hit.position + Vec3::Y * BAR_OFFSET,
+                    timer: Timer::from_seconds(DAMAGE_NUMBER_DURATION, false),
+                },
+                GameTag,
+            ));
+    }
+}
+
+fn float_damage_numbers(
+    mut commands: Commands,
+    mut numbers: Query<(
+        Entity,
+        &mut DamageNumber,
+        &mut Style,
+        &mut Text,
+        &mut Visibility,
+    )>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    time: Res<Time>,
+    damage_numbers: Res<DamageNumbers>,
+) {
+    let (camera, camera_transform) = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    for (entity, mut number, mut style, mut text, mut visibility) in &mut numbers {
+        if !damage_numbers.0 || number.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let percent = number.timer.percent();
+        let position = number.position + Vec3::Y * 0.2 * percent;
+        match camera.world_to_viewport(camera_transform, position) {
+            Some(screen) => {
+                style.position = UiRect {
+                    left: Val::Px(screen.x),
+                    bottom: Val::Px(screen.y),
+                    ..default()
+                };
+                visibility.is_visible = true;
+            }
+            None => visibility.is_visible = false,
+        }
+        text.sections[0].style.color.set_a(1.0 - percent);
+    }
+}
@@ -8,18 +8,66 @@ use bevy::{
     utils::{Entry, HashMap},
 };
 use bracket_noise::prelude::*;
+use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::game::terra::Plane;
 
-const WATER_LEVEL: f32 = -10.0;
-const PLATEAU_LEVEL: f32 = 0.25;
-pub(crate) const LOW_DEF: u32 = 5;
-pub(crate) const HIGH_DEF: u32 = 40;
-const FLATTENING: f32 = 9.0;
+/// Terrain generation parameters, chosen before a run starts.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TerrainConfig {
+    /// Number of lots on each side of a tile, odd so that the crystal sits in the middle.
+    pub(crate) lots_per_tile: u32,
+    /// Number of mesh subdivisions on each side of a tile, a multiple of `lots_per_tile`.
+    pub(crate) mesh_detail: u32,
+    pub(crate) flattening: f32,
+    pub(crate) water_level: f32,
+    pub(crate) plateau_level: f32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            lots_per_tile: 5,
+            mesh_detail: 40,
+            flattening: 9.0,
+            water_level: -10.0,
+            plateau_level: 0.25,
+        }
+    }
+}
+
+impl TerrainConfig {
+    pub(crate) fn fine_grid() -> Self {
+        Self {
+            lots_per_tile: 7,
+            mesh_detail: 42,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn coarse_grid() -> Self {
+        Self {
+            lots_per_tile: 3,
+            mesh_detail: 36,
+            ..Default::default()
+        }
+    }
+
+    /// Change the mesh detail, rounded to the closest multiple of the number of lots.
+    pub(crate) fn with_mesh_detail(self, mesh_detail: u32) -> Self {
+        let per_lot = ((mesh_detail as f32 / self.lots_per_tile as f32).round() as u32).max(1);
+        Self {
+            mesh_detail: per_lot * self.lots_per_tile,
+            ..self
+        }
+    }
+}
 
 pub(crate) struct HeightMap {
     seeds: crate::game::terra::TerraNoises,
+    config: TerrainConfig,
+    plane: Plane,
     x: f32,
     y: f32,
 }
@@ -55,52 +103,59 @@ impl HeightMap {
         y: f32,
         plane: Plane,
         seeds: crate::game::terra::TerraNoises,
+        config: TerrainConfig,
     ) -> Self {
-        Self { seeds, x, y }
+        Self {
+            seeds,
+            config,
+            plane,
+            x,
+            y,
+        }
     }
 
-    fn is_obstacle(elevation: f32) -> bool {
-        !(WATER_LEVEL..=PLATEAU_LEVEL).contains(&elevation)
+    fn is_obstacle(&self, elevation: f32) -> bool {
+        !(self.config.water_level..=self.config.plateau_level).contains(&elevation)
     }
 
-    fn pretty_border(kind: u8, elevation_block: f32, x: f32, y: f32, elevation: f32) -> f32 {
+    fn pretty_border(&self, kind: u8, elevation_block: f32, x: f32, y: f32, elevation: f32) -> f32 {
         match kind {
-            0 => elevation / FLATTENING as f32,
+            0 => elevation / self.config.flattening,
             3 => {
                 if x < -y {
-                    Self::obstacle_height(elevation_block, elevation)
+                    self.obstacle_height(elevation_block, elevation)
                 } else {
-                    elevation / FLATTENING as f32
+                    elevation / self.config.flattening
                 }
             }
             6 => {
                 if x < y {
-                    Self::obstacle_height(elevation_block, elevation)
+                    self.obstacle_height(elevation_block, elevation)
                 } else {
-                    elevation / FLATTENING as f32
+                    elevation / self.config.flattening
                 }
             }
             9 => {
                 if x > y {
-                    Self::obstacle_height(elevation_block, elevation)
+                    self.obstacle_height(elevation_block, elevation)
                 } else {
-                    elevation / FLATTENING as f32
+                    elevation / self.config.flattening
                 }
             }
             12 => {
                 if -x < y {
-                    Self::obstacle_height(elevation_block, elevation)
+                    self.obstacle_height(elevation_block, elevation)
                 } else {
-                    elevation / FLATTENING as f32
+                    elevation / self.config.flattening
                 }
             }
-            _ => Self::obstacle_height(elevation_block, elevation),
+            _ => self.obstacle_height(elevation_block, elevation),
         }
     }
 
-    fn obstacle_height(elevation_block: f32, elevation: f32) -> f32 {
-        if elevation_block > PLATEAU_LEVEL as f32 {
-            (elevation - (1.0 - PLATEAU_LEVEL as f32)) / FLATTENING as f32 + 0.4
+    fn obstacle_height(&self, elevation_block: f32, elevation: f32) -> f32 {
+        if elevation_block > self.config.plateau_level {
+            (elevation - (1.0 - self.config.plateau_level)) / self.config.flattening + 0.4
         } else {
             elevation
         }
@@ -126,18 +181,28 @@ impl HeightMap {
 
     #[instrument(skip(self))]
     pub(crate) fn into_mesh_and_texture(self) -> Terrain {
-        let (material_elevation_noise, material_simplified_elevation_noise) =
-            Self::get_noises(self.seeds.material_seed as u64);
+        let (elevation_noise, simplified_elevation_noise) = Self::get_noises(match self.plane {
+            Plane::Material => self.seeds.material_seed,
+            Plane::Ethereal => self.seeds.ethereal_seed,
+        } as u64);
 
         fn color_to_vec3(color: Color) -> Vec3 {
             Vec3::new(color.r(), color.g(), color.b())
         }
-        let material_mountains = color_to_vec3(Color::hex("FFFAFA").unwrap());
-        let material_plains = color_to_vec3(Color::hex("A3C058").unwrap());
-        let ethereal_mountains = color_to_vec3(Color::VIOLET);
-        let ethereal_plains = color_to_vec3(Color::ORANGE_RED);
-        let low = LOW_DEF as f32;
-        let high = HIGH_DEF as f32;
+        let (plains, mountains) = match self.plane {
+            Plane::Material => (
+                color_to_vec3(Color::hex("A3C058").unwrap()),
+                color_to_vec3(Color::hex("FFFAFA").unwrap()),
+            ),
+            Plane::Ethereal => (
+                color_to_vec3(Color::ORANGE_RED),
+                color_to_vec3(Color::VIOLET),
+            ),
+        };
+        let low_def = self.config.lots_per_tile;
+        let high_def = self.config.mesh_detail;
+        let low = low_def as f32;
+        let high = high_def as f32;
         let error_margin = 1.0 / high / 2.0;
 
         #[allow(clippy::type_complexity)]
@@ -148,25 +213,22 @@ impl HeightMap {
             Vec<[f32; 3]>, // normals
             Vec<[f32; 2]>, // uvs
             Vec<IVec2>,    // simplified map
-            Vec<u8>,       // colors material
-            Vec<u8>,       // colors ethereal
+            Vec<u8>,       // colors
             Vec<u8>,       // metallic_roughness
         ) {
-            // let mut simplified_vertices = Vec::with_capacity(LOW_DEF as usize * LOW_DEF as usize);
-            let mut map = Vec::with_capacity(LOW_DEF as usize * LOW_DEF as usize);
-            let mut vertices = Vec::with_capacity(HIGH_DEF as usize * HIGH_DEF as usize);
-            let mut normals = Vec::with_capacity(HIGH_DEF as usize * HIGH_DEF as usize);
-            let mut uvs = Vec::with_capacity(HIGH_DEF as usize * HIGH_DEF as usize);
-            let mut colors_material = Vec::with_capacity(LOW_DEF as usize * LOW_DEF as usize);
-            let mut colors_ethereal = Vec::with_capacity(LOW_DEF as usize * LOW_DEF as usize);
-            let mut metallic_roughness = Vec::with_capacity(LOW_DEF as usize * LOW_DEF as usize);
+            let mut map = Vec::with_capacity(low_def as usize * low_def as usize);
+            let mut vertices = Vec::with_capacity(high_def as usize * high_def as usize);
+            let mut normals = Vec::with_capacity(high_def as usize * high_def as usize);
+            let mut uvs = Vec::with_capacity(high_def as usize * high_def as usize);
+            let mut colors = Vec::with_capacity(low_def as usize * low_def as usize);
+            let mut metallic_roughness = Vec::with_capacity(low_def as usize * low_def as usize);
             let mut cached = CachedNoise::new(simplified_noise);
-            for i in 0..=HIGH_DEF {
-                for j in 0..=HIGH_DEF {
-                    let xz = (i as f32 / HIGH_DEF as f32, j as f32 / HIGH_DEF as f32);
+            for i in 0..=high_def {
+                for j in 0..=high_def {
+                    let xz = (i as f32 / high_def as f32, j as f32 / high_def as f32);
                     let xz_low = (
-                        ((xz.0 * LOW_DEF as f32) as u32) as f32 / LOW_DEF as f32,
-                        ((xz.1 * LOW_DEF as f32) as u32) as f32 / LOW_DEF as f32,
+                        ((xz.0 * low_def as f32) as u32) as f32 / low_def as f32,
+                        ((xz.1 * low_def as f32) as u32) as f32 / low_def as f32,
                     );
                     let nx = self.x + xz.0;
                     let ny = self.y + xz.1;
@@ -192,40 +254,40 @@ impl HeightMap {
                         elevation_for_block(nx_low + 1.0 / low, ny_low, ixz_low.0 + 1, ixz_low.1);
                     let mut kind = 0;
 
-                    if Self::is_obstacle(elevation_block) {
-                        if Self::is_obstacle(top) {
+                    if self.is_obstacle(elevation_block) {
+                        if self.is_obstacle(top) {
                             kind |= 1;
                         }
-                        if Self::is_obstacle(bottom) {
+                        if self.is_obstacle(bottom) {
                             kind |= 4;
                         }
-                        if Self::is_obstacle(left) {
+                        if self.is_obstacle(left) {
                             kind |= 2;
                         }
-                        if Self::is_obstacle(right) {
+                        if self.is_obstacle(right) {
                             kind |= 8;
                         }
                     }
 
-                    let elevation_flattened = Self::pretty_border(
+                    let elevation_flattened = self.pretty_border(
                         kind,
                         elevation_block,
-                        (xz.0 - xz_low.0) * high - (HIGH_DEF / LOW_DEF / 2) as f32 + 0.5,
-                        (xz.1 - xz_low.1) * high - (HIGH_DEF / LOW_DEF / 2) as f32 + 0.5,
+                        (xz.0 - xz_low.0) * high - (high_def / low_def) as f32 / 2.0 + 0.5,
+                        (xz.1 - xz_low.1) * high - (high_def / low_def) as f32 / 2.0 + 0.5,
                         elevation,
                     );
                     if (xz.0 - xz_low.0).abs() < error_margin
                         && (xz.1 - xz_low.1).abs() < error_margin
                     {
                         let simple_height = if kind > 0 {
-                            Self::obstacle_height(elevation_block, elevation_block)
+                            self.obstacle_height(elevation_block, elevation_block)
                         } else {
-                            elevation_block / FLATTENING
+                            elevation_block / self.config.flattening
                         };
-                        if simple_height > PLATEAU_LEVEL {
+                        if simple_height > self.config.plateau_level {
                             map.push(IVec2::new(
-                                (xz.0 * LOW_DEF as f32) as i32,
-                                (xz.1 * LOW_DEF as f32) as i32,
+                                (xz.0 * low_def as f32) as i32,
+                                (xz.1 * low_def as f32) as i32,
                             ));
                         }
                     }
@@ -237,15 +299,8 @@ impl HeightMap {
                         && (xz.1 - xz_low.1).abs() < error_margin
                     {
                         let elevation = elevation + 0.3;
-                        let lerped = material_plains.lerp(material_mountains, elevation);
-                        colors_material.extend_from_slice(&[
-                            (lerped.x * 255.0) as u8,
-                            (lerped.y * 255.0) as u8,
-                            (lerped.z * 255.0) as u8,
-                            255,
-                        ]);
-                        let lerped = ethereal_plains.lerp(ethereal_mountains, elevation);
-                        colors_ethereal.extend_from_slice(&[
+                        let lerped = plains.lerp(mountains, elevation);
+                        colors.extend_from_slice(&[
                             (lerped.x * 255.0) as u8,
                             (lerped.y * 255.0) as u8,
                             (lerped.z * 255.0) as u8,
@@ -262,58 +317,30 @@ impl HeightMap {
                     }
                 }
             }
-            (
-                vertices,
-                normals,
-                uvs,
-                map,
-                colors_material,
-                colors_ethereal,
-                metallic_roughness,
-            )
+            (vertices, normals, uvs, map, colors, metallic_roughness)
         };
 
-        let (
-            positions,
-            normals,
-            uvs,
-            simplified_map,
-            material_colors,
-            ethereal_colors,
-            metallic_roughness,
-        ) = generate(
-            material_elevation_noise,
-            material_simplified_elevation_noise,
-        );
-        let mesh = vertices_as_mesh(positions, normals, uvs, HIGH_DEF);
+        let (positions, normals, uvs, simplified_map, colors, metallic_roughness) =
+            generate(elevation_noise, simplified_elevation_noise);
+        let mesh = vertices_as_mesh(positions, normals, uvs, high_def);
 
         Terrain {
             mesh,
             simplified_map,
-            material_color: Image::new(
-                Extent3d {
-                    width: LOW_DEF + 1,
-                    height: LOW_DEF + 1,
-                    depth_or_array_layers: 1,
-                },
-                TextureDimension::D2,
-                material_colors,
-                TextureFormat::Rgba8UnormSrgb,
-            ),
-            ethereal_color: Image::new(
+            color: Image::new(
                 Extent3d {
-                    width: LOW_DEF + 1,
-                    height: LOW_DEF + 1,
+                    width: low_def + 1,
+                    height: low_def + 1,
                     depth_or_array_layers: 1,
                 },
                 TextureDimension::D2,
-                ethereal_colors,
+                colors,
                 TextureFormat::Rgba8UnormSrgb,
             ),
             metallic_roughness: Image::new(
                 Extent3d {
-                    width: LOW_DEF + 1,
-                    height: LOW_DEF + 1,
+                    width: low_def + 1,
+                    height: low_def + 1,
                     depth_or_array_layers: 1,
                 },
                 TextureDimension::D2,
@@ -393,7 +420,6 @@ fn vertices_as_mesh(
 pub(crate) struct Terrain {
     pub(crate) simplified_map: Vec<IVec2>,
     pub(crate) mesh: Mesh,
-    pub(crate) material_color: Image,
-    pub(crate) ethereal_color: Image,
+    pub(crate) color: Image,
     pub(crate) metallic_roughness: Image,
 }
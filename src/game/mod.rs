@@ -1,14 +1,36 @@
+pub(crate) mod ai;
+pub(crate) mod archetypes;
+pub(crate) mod balance;
 pub(crate) mod builder;
+pub(crate) mod difficulty;
+pub(crate) mod effects;
+pub(crate) mod flow_field;
+pub(crate) mod health_bars;
 pub(crate) mod heightmap;
 pub(crate) mod nests;
+pub(crate) mod path_preview;
+pub(crate) mod save;
+pub(crate) mod spatial;
 pub(crate) mod stats;
 pub(crate) mod switcher;
 pub(crate) mod terra;
 pub(crate) mod terrain_spawner;
+pub(crate) mod timestep;
 pub(crate) mod towers;
 pub(crate) mod ui;
+pub(crate) mod waves;
 pub(crate) mod zombies;
 
+use bevy::{
+    ecs::schedule::{
+        RunCriteria, RunCriteriaDescriptor, RunCriteriaDescriptorCoercion, RunCriteriaLabel,
+        ShouldRun,
+    },
+    prelude::{In, Res, State, SystemSet},
+};
+
+use crate::GameState;
+
 #[derive(Clone, PartialEq, Debug, Eq, Hash)]
 pub(crate) enum PlayingState {
     Playing,
@@ -16,18 +38,80 @@ pub(crate) enum PlayingState {
     Building,
 }
 
+impl PlayingState {
+    const ALL: [PlayingState; 3] = [
+        PlayingState::Playing,
+        PlayingState::SwitchingPlane,
+        PlayingState::Building,
+    ];
+
+    fn update_label(&self) -> PlayingUpdate {
+        match self {
+            PlayingState::Playing => PlayingUpdate::Playing,
+            PlayingState::SwitchingPlane => PlayingUpdate::SwitchingPlane,
+            PlayingState::Building => PlayingUpdate::Building,
+        }
+    }
+}
+
+/// Labels of the `on_update` run criteria of each [`PlayingState`].
+#[derive(RunCriteriaLabel, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum PlayingUpdate {
+    Playing,
+    SwitchingPlane,
+    Building,
+}
+
+/// Run criteria for systems updating while in `state`, that stop while the game is paused.
+pub(crate) fn on_playing_update(state: PlayingState) -> RunCriteriaDescriptor {
+    RunCriteria::pipe(state.update_label(), unless_paused)
+}
+
+fn unless_paused(In(should_run): In<ShouldRun>, game_state: Res<State<GameState>>) -> ShouldRun {
+    if *game_state.current() == GameState::Playing {
+        should_run
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Everything needed to play the game: the simulation and how it's shown.
 pub(crate) struct Plugin;
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_state(PlayingState::Playing)
+        app.add_plugin(SimulationPlugin)
+            .add_plugin(terrain_spawner::TerrainCameraPlugin)
+            .add_plugin(ui::Plugin)
+            .add_plugin(path_preview::Plugin)
+            .add_plugin(health_bars::Plugin);
+    }
+}
+
+/// Game logic only, that can run without a window or a renderer.
+pub(crate) struct SimulationPlugin;
+impl bevy::app::Plugin for SimulationPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_state(PlayingState::Playing);
+        for state in PlayingState::ALL {
+            let label = state.update_label();
+            app.add_system_set(
+                SystemSet::new().with_run_criteria(State::on_update(state).label(label)),
+            );
+        }
+        app.add_plugin(timestep::Plugin)
+            .add_plugin(balance::Plugin)
             .add_plugin(stats::Plugin)
             .add_plugin(terrain_spawner::TerrainSpawnerPlugin)
             .add_plugin(terra::TerraPlugin)
             .add_plugin(switcher::Plugin)
-            .add_plugin(ui::Plugin)
             .add_plugin(builder::Plugin)
             .add_plugin(nests::Plugin)
+            .add_plugin(waves::Plugin)
             .add_plugin(zombies::Plugin)
-            .add_plugin(towers::Plugin);
+            .add_plugin(effects::Plugin)
+            .add_plugin(spatial::Plugin)
+            .add_plugin(towers::Plugin)
+            .add_plugin(save::Plugin)
+            .add_plugin(ai::Plugin);
     }
 }
@@ -1,53 +1,102 @@
 use std::f32::consts::PI;
 
 use bevy::prelude::*;
-use rand::seq::SliceRandom;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 
 use crate::{assets::ZombieAssets, GameState};
 
 use super::{
+    archetypes::ZombieArchetypes,
+    balance::Balance,
+    difficulty::Difficulty,
+    effects::StatusEffects,
+    heightmap::TerrainConfig,
     stats::{GameTag, Stats},
-    terra::Plane,
+    terra::{Plane, RunSeed},
     terrain_spawner::map_to_world,
-    zombies::IdleZombie,
+    timestep::{playing, Interpolated, SimulationStage},
+    waves::{direct_waves, NestSpawn, WaveDirector},
+    zombies::{IdleZombie, ZombieKind},
 };
 
 pub(crate) struct Plugin;
 
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_update(GameState::Playing).with_system(spawn_zombies));
+        app.insert_resource(ZombieRng(StdRng::from_entropy()))
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(playing)
+                    .with_system(spawn_zombies.after(direct_waves)),
+            );
     }
 }
 
+/// Random generator used when spawning zombies, seeded from the [`RunSeed`].
+pub(crate) struct ZombieRng(pub(crate) StdRng);
+
+fn setup(mut commands: Commands, seed: Res<RunSeed>) {
+    commands.insert_resource(ZombieRng(StdRng::seed_from_u64(seed.0)));
+}
+
 #[derive(Component)]
 pub(crate) struct ZombieNest {
     pub(crate) map: IVec2,
     pub(crate) lot: IVec2,
-    pub(crate) timer: Timer,
 }
 
 fn spawn_zombies(
     mut commands: Commands,
-    mut nests: Query<&mut ZombieNest>,
+    mut spawns: EventReader<NestSpawn>,
     zombie_assets: Res<ZombieAssets>,
-    time: Res<Time>,
+    archetypes: Res<Assets<ZombieArchetypes>>,
+    director: Res<WaveDirector>,
+    difficulty: Res<Difficulty>,
+    balance: Res<Balance>,
     plane: Res<Plane>,
     stats: Res<Stats>,
+    mut rng: ResMut<ZombieRng>,
+    config: Res<TerrainConfig>,
 ) {
-    let mut rng = rand::thread_rng();
-    for mut nest in &mut nests {
-        if nest.timer.tick(time.delta()).just_finished() {
-            let position = map_to_world((nest.map, nest.lot));
+    let archetypes = match archetypes.get(&zombie_assets.archetypes) {
+        Some(archetypes) => archetypes,
+        None => return,
+    };
+    let elapsed = stats.time.elapsed_secs();
+    let difficulty = balance.difficulty(*difficulty);
+    let available = archetypes
+        .archetypes
+        .iter()
+        .filter(|archetype| archetype.from_wave <= director.wave)
+        .collect::<Vec<_>>();
+    for spawn in spawns.iter() {
+        let archetype = match available.choose_weighted(&mut rng.0, |archetype| archetype.weight) {
+            Ok(archetype) => *archetype,
+            Err(_) => continue,
+        };
+        let position = map_to_world((spawn.map, spawn.lot), &config);
+        let zombie_plane = *[Plane::Material, Plane::Ethereal]
+            .choose(&mut rng.0)
+            .unwrap();
 
-            let mut transform = Transform::from_xyz(position.x, 0.2, position.y)
-                .looking_at(Vec3::ZERO, Vec3::Y)
-                .with_scale(Vec3::splat(0.05));
+        for _ in 0..archetype.count {
+            // spread a group a little so they don't all overlap
+            let jitter = if archetype.count > 1 {
+                Vec2::new(rng.0.gen_range(-0.05..0.05), rng.0.gen_range(-0.05..0.05))
+            } else {
+                Vec2::ZERO
+            };
+            let mut transform =
+                Transform::from_xyz(position.x + jitter.x, 0.2, position.y + jitter.y)
+                    .looking_at(Vec3::ZERO, Vec3::Y)
+                    .with_scale(Vec3::splat(archetype.scale));
             transform.rotate(Quat::from_rotation_y(PI));
-            let zombie_plane = *[Plane::Material, Plane::Ethereal].choose(&mut rng).unwrap();
+            let life = archetype.health.at(elapsed, director.wave) * difficulty.zombie_health;
             commands
                 .spawn_bundle(SceneBundle {
-                    scene: zombie_assets.zombie.clone_weak(),
+                    scene: archetype.scene.clone_weak(),
                     transform,
                     visibility: Visibility {
                         is_visible: *plane == zombie_plane,
@@ -57,9 +106,18 @@ fn spawn_zombies(
                 .insert_bundle((
                     IdleZombie {
                         plane: zombie_plane,
-                        life: stats.time.elapsed_secs() / 6.0,
-                        speed: stats.time.elapsed_secs() / 2000.0,
+                        life,
+                        speed: archetype.speed.at(elapsed, director.wave) * difficulty.zombie_speed,
+                    },
+                    ZombieKind {
+                        archetype: archetype.name.clone(),
+                        reward: (archetype.reward as f32 * difficulty.reward).round() as u32,
+                        damage: archetype.damage,
+                        tower_damage: archetype.tower_damage,
+                        max_life: life,
                     },
+                    StatusEffects::default(),
+                    Interpolated::new(transform),
                     GameTag,
                 ));
         }
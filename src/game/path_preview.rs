@@ -0,0 +1,222 @@
+use bevy::{pbr::NotShadowCaster, prelude::*, render::mesh::PrimitiveTopology};
+
+use crate::assets::UiAssets;
+
+use super::{
+    builder::blocks_a_nest,
+    heightmap::TerrainConfig,
+    nests::ZombieNest,
+    on_playing_update,
+    terra::Plane,
+    terrain_spawner::{map_to_world, CursorPosition, Map, NavMesh, Pathfinding},
+    PlayingState,
+};
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PreviewMaterials>()
+            .add_system_set(SystemSet::on_enter(PlayingState::Building).with_system(setup))
+            .add_system_set(SystemSet::on_exit(PlayingState::Building).with_system(clear))
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(on_playing_update(PlayingState::Building))
+                    .with_system(preview_paths),
+            );
+    }
+}
+
+struct PreviewMaterials {
+    current: Handle<StandardMaterial>,
+    after: Handle<StandardMaterial>,
+}
+
+impl FromWorld for PreviewMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        PreviewMaterials {
+            current: materials.add(StandardMaterial {
+                base_color: Color::rgba(0.3, 0.6, 1.0, 0.8),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+            after: materials.add(StandardMaterial {
+                base_color: Color::rgba(1.0, 0.9, 0.2, 0.8),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+/// Routes of the zombies, before or after building at the cursor.
+#[derive(Component, Clone, Copy, PartialEq)]
+enum PathPreview {
+    Current,
+    After,
+}
+
+/// Message shown when building at the cursor would cut the crystal from a nest.
+#[derive(Component)]
+struct BlockedWarning;
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    materials: Res<PreviewMaterials>,
+    ui_handles: Res<UiAssets>,
+) {
+    for (preview, material) in [
+        (PathPreview::Current, &materials.current),
+        (PathPreview::After, &materials.after),
+    ] {
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: meshes.add(routes_mesh(vec![])),
+                material: material.clone_weak(),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert_bundle((preview, NotShadowCaster));
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position: UiRect {
+                    bottom: Val::Px(70.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Px(30.0)),
+                justify_content: JustifyContent::Center,
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            color: UiColor(Color::NONE),
+            ..default()
+        })
+        .insert(BlockedWarning)
+        .with_children(|builder| {
+            builder.spawn_bundle(TextBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font: ui_handles.font_sub.clone_weak(),
+                        color: Color::rgb(1.0, 0.3, 0.3),
+                        font_size: 25.,
+                    },
+                ),
+                ..default()
+            });
+        });
+}
+
+fn clear(
+    mut commands: Commands,
+    previews: Query<Entity, Or<(With<PathPreview>, With<BlockedWarning>)>>,
+) {
+    for entity in &previews {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Line segments following each path, slightly above the ground.
+fn routes_mesh(routes: Vec<Vec<Vec2>>) -> Mesh {
+    let positions = routes
+        .iter()
+        .flat_map(|route| route.windows(2))
+        .flat_map(|segment| {
+            [
+                [segment[0].x, 0.08, segment[0].y],
+                [segment[1].x, 0.08, segment[1].y],
+            ]
+        })
+        .collect::<Vec<_>>();
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[0.0, 1.0, 0.0]; positions.len()],
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh
+}
+
+/// Path from a nest to the crystal on a navmesh, `None` if the crystal can't be reached.
+fn route(navmesh: &NavMesh, from: Vec2) -> Option<Vec<Vec2>> {
+    let path = navmesh.mesh.path(from, Vec2::ZERO);
+    path.complete.then(|| {
+        let mut route = vec![from];
+        route.extend(path.path);
+        route
+    })
+}
+
+fn preview_paths(
+    cursor_position: Res<CursorPosition>,
+    pathfinding: Res<Pathfinding>,
+    nests: Query<&ZombieNest>,
+    plane: Res<Plane>,
+    map: Res<Map>,
+    config: Res<TerrainConfig>,
+    mut previews: Query<(&PathPreview, &Handle<Mesh>, &mut Visibility)>,
+    added: Query<(), Added<PathPreview>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    warning: Query<&Children, With<BlockedWarning>>,
+    mut texts: Query<&mut Text>,
+    mut previewed: Local<(IVec2, IVec2)>,
+) {
+    let coords = (cursor_position.map, cursor_position.lot);
+    if *previewed == coords && !pathfinding.is_changed() && added.is_empty() {
+        return;
+    }
+    *previewed = coords;
+
+    let starts = nests
+        .iter()
+        .map(|nest| map_to_world((nest.map, nest.lot), &config))
+        .collect::<Vec<_>>();
+    let current = starts
+        .iter()
+        .filter_map(|start| route(pathfinding.plane(*plane), *start))
+        .collect::<Vec<_>>();
+
+    let free = map.is_buildable(coords);
+    // same check as when building, on both planes
+    let blocking = free && blocks_a_nest(coords, &pathfinding, nests.iter(), &config);
+    let mut after = vec![];
+    if free && !blocking {
+        // routes are only drawn for the previewed plane
+        let mut temp_mesh = pathfinding.plane(*plane).clone();
+        temp_mesh.cut_polygon_out(coords, &config);
+        after = starts
+            .iter()
+            .filter_map(|start| route(&temp_mesh, *start))
+            .collect();
+    }
+
+    for (preview, mesh, mut visibility) in &mut previews {
+        let routes = match preview {
+            PathPreview::Current => current.clone(),
+            PathPreview::After => after.clone(),
+        };
+        visibility.is_visible = !routes.is_empty();
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            *mesh = routes_mesh(routes);
+        }
+    }
+    for children in &warning {
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = if blocking {
+                    "building here would block the crystal".to_string()
+                } else {
+                    "".to_string()
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,531 @@
+use std::{f32::consts::PI, time::Duration};
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*, time::Stopwatch, utils::HashMap};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{assets::ZombieAssets, GameState};
+
+use super::{
+    archetypes::ZombieArchetypes,
+    difficulty::Difficulty,
+    effects::StatusEffects,
+    flow_field::PathfindingMode,
+    heightmap::TerrainConfig,
+    nests::{ZombieNest, ZombieRng},
+    stats::{GameTag, Stats},
+    switcher::ETHEREAL_LIGHT,
+    terra::{Plane, TerraNoises},
+    terrain_spawner::{Map, Occupying},
+    timestep::Interpolated,
+    towers::{Targeting, Tower, TowerKind},
+    waves::{WaveDirector, WaveEvent},
+    zombies::{IdleZombie, Zombie, ZombieKind},
+};
+
+/// Bumped every time the format changes, older saves are ignored.
+const SAVE_VERSION: u32 = 11;
+
+#[cfg(not(target_arch = "wasm32"))]
+const SAVE_PATH: &str = "lain.save.ron";
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingLoad>()
+            .init_resource::<SavedTiles>()
+            .add_event::<SaveRequested>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(save_shortcut.before(save_game))
+                    .with_system(restore_game),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(in_game)
+                    .with_system(save_game),
+            );
+    }
+}
+
+/// Ask for the running game to be saved.
+pub(crate) struct SaveRequested;
+
+/// Game to restore when entering [`GameState::Playing`].
+#[derive(Default)]
+pub(crate) struct PendingLoad(pub(crate) Option<SaveGame>);
+
+/// Saved content of tiles that haven't been generated again yet, keyed by tile position.
+#[derive(Default)]
+pub(crate) struct SavedTiles(pub(crate) HashMap<IVec2, SavedTile>);
+
+#[derive(Serialize, Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SaveGame {
+    version: u32,
+    pub(crate) seed: u64,
+    /// Lot coordinates only make sense with the same terrain.
+    terrain: TerrainConfig,
+    pathfinding: PathfindingMode,
+    /// Seed the zombie generator restarts from, for the rest of the run to play the same.
+    zombie_rng: u64,
+    difficulty: Difficulty,
+    plane: Plane,
+    stats: SavedStats,
+    waves: WaveDirector,
+    tiles: Vec<SavedTile>,
+    towers: Vec<SavedTower>,
+    zombies: Vec<SavedZombie>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedStats {
+    life: u32,
+    time: f32,
+    credits: u32,
+    killed: u32,
+}
+
+/// Everything on a tile that isn't generated from its heightmap.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(crate) struct SavedTile {
+    position: (i32, i32),
+    lots: Vec<(Plane, (i32, i32), Occupying)>,
+    nests: Vec<(i32, i32)>,
+}
+
+impl SavedTile {
+    pub(crate) fn lots(&self, plane: Plane) -> impl Iterator<Item = (IVec2, Occupying)> + '_ {
+        self.lots
+            .iter()
+            .filter(move |(lot_plane, _, _)| *lot_plane == plane)
+            .map(|(_, (x, y), occupying)| (IVec2::new(*x, *y), occupying.clone()))
+    }
+
+    pub(crate) fn nests(&self) -> impl Iterator<Item = ZombieNest> + '_ {
+        let (x, y) = self.position;
+        self.nests.iter().map(move |(lot_x, lot_y)| ZombieNest {
+            map: IVec2::new(x, y),
+            lot: IVec2::new(*lot_x, *lot_y),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedTower {
+    translation: [f32; 3],
+    kind: TowerKind,
+    plane: Plane,
+    strength: f32,
+    range: f32,
+    reload: f32,
+    elapsed: f32,
+    targeting: Targeting,
+    health: f32,
+    max_health: f32,
+    level: u32,
+    kills: u32,
+    damage: f32,
+    invested: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedZombie {
+    translation: [f32; 3],
+    plane: Plane,
+    life: f32,
+    speed: f32,
+    archetype: String,
+    reward: u32,
+    damage: u32,
+    tower_damage: f32,
+    max_life: f32,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn read_save(config: &TerrainConfig) -> Option<SaveGame> {
+    let content = std::fs::read_to_string(SAVE_PATH).ok()?;
+    parse_save(&content, config)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn read_save(_config: &TerrainConfig) -> Option<SaveGame> {
+    None
+}
+
+/// Read a save, ignoring it when it's from another version or made with another terrain.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+fn parse_save(content: &str, config: &TerrainConfig) -> Option<SaveGame> {
+    let header: SaveHeader = ron::from_str(content)
+        .map_err(|err| warn!("couldn't read save: {}", err))
+        .ok()?;
+    if header.version != SAVE_VERSION {
+        warn!(
+            "ignoring save with version {}, expected {}",
+            header.version, SAVE_VERSION
+        );
+        return None;
+    }
+    let save: SaveGame = ron::from_str(content)
+        .map_err(|err| warn!("couldn't read save: {}", err))
+        .ok()?;
+    if save.terrain != *config {
+        warn!(
+            "ignoring save made with terrain {:?}, expected {:?}",
+            save.terrain, config
+        );
+        return None;
+    }
+    Some(save)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_save(save: &SaveGame) {
+    match ron::ser::to_string(save) {
+        Ok(content) => match std::fs::write(SAVE_PATH, content) {
+            Ok(()) => info!("game saved to {}", SAVE_PATH),
+            Err(err) => warn!("couldn't write save: {}", err),
+        },
+        Err(err) => warn!("couldn't serialize save: {}", err),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_save(_save: &SaveGame) {
+    warn!("saving is not supported on this platform");
+}
+
+fn setup(mut pending: ResMut<PendingLoad>, mut saved_tiles: ResMut<SavedTiles>) {
+    saved_tiles.0 = pending
+        .0
+        .as_mut()
+        .map(|save| {
+            save.tiles
+                .drain(..)
+                .map(|tile| (IVec2::new(tile.position.0, tile.position.1), tile))
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
+/// Saving is also possible from the pause menu.
+fn in_game(state: Res<State<GameState>>) -> ShouldRun {
+    match state.current() {
+        GameState::Playing | GameState::Paused => ShouldRun::Yes,
+        _ => ShouldRun::No,
+    }
+}
+
+fn save_shortcut(keyboard_input: Res<Input<KeyCode>>, mut save: EventWriter<SaveRequested>) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        save.send(SaveRequested);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn save_game(
+    mut requests: EventReader<SaveRequested>,
+    noises: Res<TerraNoises>,
+    difficulty: Res<Difficulty>,
+    plane: Res<Plane>,
+    stats: Res<Stats>,
+    director: Res<WaveDirector>,
+    map: Res<Map>,
+    saved_tiles: Res<SavedTiles>,
+    nests: Query<&ZombieNest>,
+    towers: Query<(&Tower, &Transform)>,
+    (config, pathfinding, mut zombie_rng): (
+        Res<TerrainConfig>,
+        Res<PathfindingMode>,
+        ResMut<ZombieRng>,
+    ),
+    zombies: Query<
+        (
+            &Transform,
+            &ZombieKind,
+            Option<&Zombie>,
+            Option<&IdleZombie>,
+        ),
+        Or<(With<Zombie>, With<IdleZombie>)>,
+    >,
+) {
+    if requests.iter().count() == 0 {
+        return;
+    }
+
+    let mut tiles: HashMap<IVec2, SavedTile> = HashMap::default();
+    for ((position, lot_plane), lots) in &map.lots {
+        let tile = tiles.entry(*position).or_insert_with(|| SavedTile {
+            position: (position.x, position.y),
+            ..default()
+        });
+        tile.lots.extend(
+            lots.iter()
+                .filter(|(_, occupying)| !matches!(occupying, Occupying::Mountain))
+                .map(|(lot, occupying)| (*lot_plane, (lot.x, lot.y), occupying.clone())),
+        );
+    }
+    for nest in &nests {
+        if let Some(tile) = tiles.get_mut(&nest.map) {
+            tile.nests.push((nest.lot.x, nest.lot.y));
+        }
+    }
+    let mut tiles = tiles.into_values().collect::<Vec<_>>();
+    // tiles from a previous save that haven't been visited yet in this session
+    tiles.extend(saved_tiles.0.values().cloned());
+    // the generator can't be saved, it restarts from a seed both here and when restoring
+    let zombie_seed = zombie_rng.0.gen();
+    zombie_rng.0 = StdRng::seed_from_u64(zombie_seed);
+
+    write_save(&SaveGame {
+        version: SAVE_VERSION,
+        seed: noises.seed,
+        terrain: *config,
+        pathfinding: *pathfinding,
+        zombie_rng: zombie_seed,
+        difficulty: *difficulty,
+        plane: *plane,
+        stats: SavedStats {
+            life: stats.life,
+            time: stats.time.elapsed_secs(),
+            credits: stats.credits,
+            killed: stats.killed,
+        },
+        waves: director.clone(),
+        tiles,
+        towers: towers
+            .iter()
+            .map(|(tower, transform)| SavedTower {
+                translation: transform.translation.to_array(),
+                kind: tower.kind,
+                plane: tower.plane,
+                strength: tower.strength,
+                range: tower.range,
+                reload: tower.timer.duration().as_secs_f32(),
+                elapsed: tower.timer.elapsed_secs(),
+                targeting: tower.targeting,
+                health: tower.health,
+                max_health: tower.max_health,
+                level: tower.level,
+                kills: tower.kills,
+                damage: tower.damage,
+                invested: tower.invested,
+            })
+            .collect(),
+        zombies: zombies
+            .iter()
+            .filter_map(|(transform, kind, zombie, idle)| {
+                zombie
+                    .map(|zombie| (zombie.plane, zombie.life, zombie.speed))
+                    .or_else(|| idle.map(|idle| (idle.plane, idle.life, idle.speed)))
+                    .map(|(plane, life, speed)| SavedZombie {
+                        translation: transform.translation.to_array(),
+                        plane,
+                        life,
+                        speed,
+                        archetype: kind.archetype.clone(),
+                        reward: kind.reward,
+                        damage: kind.damage,
+                        tower_damage: kind.tower_damage,
+                        max_life: kind.max_life,
+                    })
+            })
+            .collect(),
+    });
+}
+
+fn restore_game(
+    mut commands: Commands,
+    mut pending: ResMut<PendingLoad>,
+    zombie_assets: Res<ZombieAssets>,
+    archetypes: Res<Assets<ZombieArchetypes>>,
+    mut light: Query<&mut DirectionalLight>,
+    mut wave_events: EventWriter<WaveEvent>,
+) {
+    let save = match pending.0.take() {
+        Some(save) => save,
+        None => return,
+    };
+    info!("restoring game with seed {}", save.seed);
+
+    let mut time = Stopwatch::new();
+    time.tick(Duration::from_secs_f32(save.stats.time));
+    commands.insert_resource(Stats {
+        life: save.stats.life,
+        time,
+        credits: save.stats.credits,
+        killed: save.stats.killed,
+    });
+    commands.insert_resource(save.difficulty);
+    commands.insert_resource(save.pathfinding);
+    commands.insert_resource(ZombieRng(StdRng::seed_from_u64(save.zombie_rng)));
+    wave_events.send(save.waves.status());
+    commands.insert_resource(save.waves);
+    commands.insert_resource(save.plane);
+    if save.plane == Plane::Ethereal {
+        light.for_each_mut(|mut light| light.color = ETHEREAL_LIGHT);
+    }
+
+    for tower in save.towers {
+        let mut timer = Timer::from_seconds(tower.reload, true);
+        timer.set_elapsed(Duration::from_secs_f32(tower.elapsed));
+        commands.spawn_bundle((
+            Tower {
+                kind: tower.kind,
+                timer,
+                strength: tower.strength,
+                range: tower.range,
+                plane: tower.plane,
+                targeting: tower.targeting,
+                health: tower.health,
+                max_health: tower.max_health,
+                level: tower.level,
+                kills: tower.kills,
+                damage: tower.damage,
+                invested: tower.invested,
+            },
+            Transform::from_translation(Vec3::from_array(tower.translation)),
+            GameTag,
+        ));
+    }
+
+    let archetypes = archetypes.get(&zombie_assets.archetypes);
+    for zombie in save.zombies {
+        let (scene, scale) = archetypes
+            .and_then(|archetypes| archetypes.get(&zombie.archetype))
+            .map(|archetype| (archetype.scene.clone_weak(), archetype.scale))
+            .unwrap_or_else(|| (zombie_assets.zombie.clone_weak(), 0.05));
+        let mut transform = Transform::from_translation(Vec3::from_array(zombie.translation))
+            .looking_at(Vec3::ZERO, Vec3::Y)
+            .with_scale(Vec3::splat(scale));
+        transform.rotate(Quat::from_rotation_y(PI));
+        commands
+            .spawn_bundle(SceneBundle {
+                scene,
+                transform,
+                visibility: Visibility {
+                    is_visible: save.plane == zombie.plane,
+                },
+                ..default()
+            })
+            .insert_bundle((
+                IdleZombie {
+                    plane: zombie.plane,
+                    life: zombie.life,
+                    speed: zombie.speed,
+                },
+                ZombieKind {
+                    archetype: zombie.archetype,
+                    reward: zombie.reward,
+                    damage: zombie.damage,
+                    tower_damage: zombie.tower_damage,
+                    max_life: zombie.max_life,
+                },
+                StatusEffects::default(),
+                Interpolated::new(transform),
+                GameTag,
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{
+        difficulty::Difficulty,
+        flow_field::PathfindingMode,
+        heightmap::TerrainConfig,
+        terra::Plane,
+        terrain_spawner::Occupying,
+        towers::{Targeting, TowerKind},
+        waves::WaveDirector,
+    };
+
+    use super::{
+        parse_save, SaveGame, SavedStats, SavedTile, SavedTower, SavedZombie, SAVE_VERSION,
+    };
+
+    fn save() -> SaveGame {
+        SaveGame {
+            version: SAVE_VERSION,
+            seed: 42,
+            terrain: TerrainConfig::default(),
+            pathfinding: PathfindingMode::FlowField,
+            zombie_rng: 7,
+            difficulty: Difficulty::default(),
+            plane: Plane::Ethereal,
+            stats: SavedStats {
+                life: 10,
+                time: 12.5,
+                credits: 30,
+                killed: 4,
+            },
+            waves: WaveDirector::default(),
+            tiles: vec![SavedTile {
+                position: (1, -1),
+                lots: vec![
+                    (Plane::Material, (2, 3), Occupying::Tower(TowerKind::Slow)),
+                    (
+                        Plane::Ethereal,
+                        (2, 3),
+                        Occupying::Block(Some(Box::new(Occupying::Rock(0.5)))),
+                    ),
+                ],
+                nests: vec![(0, 4)],
+            }],
+            towers: vec![SavedTower {
+                translation: [1.0, 0.05, -1.0],
+                kind: TowerKind::Slow,
+                plane: Plane::Material,
+                strength: 1.5,
+                range: 0.8,
+                reload: 1.0,
+                elapsed: 0.25,
+                targeting: Targeting::Strongest,
+                health: 8.0,
+                max_health: 10.0,
+                level: 2,
+                kills: 3,
+                damage: 12.0,
+                invested: 40,
+            }],
+            zombies: vec![SavedZombie {
+                translation: [0.5, 0.0, 0.5],
+                plane: Plane::Ethereal,
+                life: 3.0,
+                speed: 0.1,
+                archetype: "walker".to_string(),
+                reward: 1,
+                damage: 1,
+                tower_damage: 0.0,
+                max_life: 5.0,
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let content = ron::ser::to_string(&save()).unwrap();
+        let read = parse_save(&content, &TerrainConfig::default()).unwrap();
+        assert_eq!(ron::ser::to_string(&read).unwrap(), content);
+    }
+
+    #[test]
+    fn reject_other_terrain() {
+        let content = ron::ser::to_string(&save()).unwrap();
+        assert!(parse_save(&content, &TerrainConfig::coarse_grid()).is_none());
+    }
+
+    #[test]
+    fn reject_other_version() {
+        let mut save = save();
+        save.version = SAVE_VERSION - 1;
+        let content = ron::ser::to_string(&save).unwrap();
+        assert!(parse_save(&content, &TerrainConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{
+    flow_field::{from_cell, to_cell},
+    heightmap::TerrainConfig,
+    terrain_spawner::world_to_map,
+    timestep::{playing, SimulationStage},
+    zombies::{move_zombies, Zombie},
+};
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ZombieGrid>().add_system_set_to_stage(
+            SimulationStage,
+            SystemSet::new()
+                .with_run_criteria(playing)
+                .with_system(index_zombies.after(move_zombies)),
+        );
+    }
+}
+
+/// Walking zombies indexed by the lot they're on, to find those near a position
+/// without going through all of them.
+#[derive(Default)]
+pub(crate) struct ZombieGrid {
+    lots: HashMap<(IVec2, IVec2), Vec<Entity>>,
+    /// Lot each zombie is indexed in.
+    indexed: HashMap<Entity, (IVec2, IVec2)>,
+}
+
+impl ZombieGrid {
+    /// Index `entity` at `position`, moving it if it was on another lot.
+    pub(crate) fn insert(&mut self, entity: Entity, position: Vec2, config: &TerrainConfig) {
+        let lot = world_to_map(position, config);
+        match self.indexed.insert(entity, lot) {
+            Some(previous) if previous == lot => return,
+            Some(previous) => self.remove_from_lot(entity, previous),
+            None => (),
+        }
+        self.lots.entry(lot).or_default().push(entity);
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity) {
+        if let Some(lot) = self.indexed.remove(&entity) {
+            self.remove_from_lot(entity, lot);
+        }
+    }
+
+    fn remove_from_lot(&mut self, entity: Entity, lot: (IVec2, IVec2)) {
+        if let Some(entities) = self.lots.get_mut(&lot) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                self.lots.remove(&lot);
+            }
+        }
+    }
+
+    /// Zombies on the lots within `radius` of `position`. Some can be a little further than
+    /// `radius`, callers should check the actual distance.
+    pub(crate) fn near<'a>(
+        &'a self,
+        position: Vec2,
+        radius: f32,
+        config: &'a TerrainConfig,
+    ) -> impl Iterator<Item = Entity> + 'a {
+        lots_near(position, radius, config)
+            .filter_map(move |lot| self.lots.get(&lot))
+            .flatten()
+            .copied()
+    }
+}
+
+/// Lots within `radius` of `position`. Some can be a little further than `radius`, callers
+/// should check the actual distance.
+pub(crate) fn lots_near(
+    position: Vec2,
+    radius: f32,
+    config: &TerrainConfig,
+) -> impl Iterator<Item = (IVec2, IVec2)> + '_ {
+    let center = to_cell(world_to_map(position, config), config);
+    let steps = (radius * config.lots_per_tile as f32).ceil() as i32;
+    (-steps..=steps)
+        .flat_map(move |x| (-steps..=steps).map(move |y| center + IVec2::new(x, y)))
+        .map(move |cell| from_cell(cell, config))
+}
+
+/// Keep the grid up to date as zombies move, only touching the lots they left or entered.
+pub(crate) fn index_zombies(
+    mut grid: ResMut<ZombieGrid>,
+    zombies: Query<(Entity, &Transform), With<Zombie>>,
+    config: Res<TerrainConfig>,
+) {
+    let gone = grid
+        .indexed
+        .keys()
+        .filter(|entity| zombies.get(**entity).is_err())
+        .copied()
+        .collect::<Vec<_>>();
+    for entity in gone {
+        grid.remove(entity);
+    }
+    for (entity, transform) in &zombies {
+        grid.insert(
+            entity,
+            Vec2::new(transform.translation.x, transform.translation.z),
+            &config,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{Entity, Vec2};
+
+    use crate::game::heightmap::TerrainConfig;
+
+    use super::ZombieGrid;
+
+    #[test]
+    fn find_near_zombies() {
+        let config = TerrainConfig::default();
+        let mut grid = ZombieGrid::default();
+        let close = Entity::from_raw(0);
+        let other_tile = Entity::from_raw(1);
+        let far = Entity::from_raw(2);
+        grid.insert(close, Vec2::new(0.1, 0.0), &config);
+        grid.insert(other_tile, Vec2::new(0.6, 0.1), &config);
+        grid.insert(far, Vec2::new(3.0, 3.0), &config);
+
+        let mut near = grid
+            .near(Vec2::new(0.3, 0.0), 0.5, &config)
+            .collect::<Vec<_>>();
+        near.sort();
+        assert_eq!(near, vec![close, other_tile]);
+        assert_eq!(
+            grid.near(Vec2::new(3.0, 2.9), 0.2, &config)
+                .collect::<Vec<_>>(),
+            vec![far]
+        );
+    }
+
+    #[test]
+    fn move_and_remove_zombies() {
+        let config = TerrainConfig::default();
+        let mut grid = ZombieGrid::default();
+        let zombie = Entity::from_raw(0);
+        grid.insert(zombie, Vec2::new(0.1, 0.0), &config);
+        grid.insert(zombie, Vec2::new(3.0, 3.0), &config);
+
+        assert_eq!(grid.near(Vec2::new(0.1, 0.0), 0.2, &config).count(), 0);
+        assert_eq!(
+            grid.near(Vec2::new(3.0, 3.0), 0.2, &config)
+                .collect::<Vec<_>>(),
+            vec![zombie]
+        );
+        grid.remove(zombie);
+        assert_eq!(grid.near(Vec2::new(3.0, 3.0), 0.2, &config).count(), 0);
+    }
+}
@@ -2,7 +2,12 @@ use bevy::{prelude::*, time::Stopwatch};
 
 use crate::GameState;
 
-use super::PlayingState;
+use super::{
+    balance::Balance,
+    difficulty::Difficulty,
+    timestep::{playing, SimulationStage, SimulationTime},
+    PlayingState,
+};
 
 #[derive(Component)]
 pub(crate) struct GameTag;
@@ -12,8 +17,14 @@ pub(crate) struct Plugin;
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Stats>()
+            .init_resource::<Difficulty>()
             .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
-            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(you_lost))
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(playing)
+                    .with_system(you_lost),
+            )
             .add_system_set(
                 SystemSet::on_exit(GameState::Playing).with_system(despawn_all_the_things),
             );
@@ -28,21 +39,32 @@ pub(crate) struct Stats {
     pub(crate) killed: u32,
 }
 
-fn setup(mut commands: Commands, mut state: ResMut<State<PlayingState>>) {
+fn setup(
+    mut commands: Commands,
+    mut state: ResMut<State<PlayingState>>,
+    difficulty: Res<Difficulty>,
+    balance: Res<Balance>,
+) {
     let _ = state.overwrite_set(PlayingState::Playing);
 
+    let difficulty = balance.difficulty(*difficulty);
     commands.insert_resource(Stats {
-        life: 20,
+        life: difficulty.life,
         time: Stopwatch::new(),
-        credits: 50,
+        credits: difficulty.credits,
         killed: 0,
     });
 }
 
-fn you_lost(mut state: ResMut<Stats>, mut game_state: ResMut<State<GameState>>, time: Res<Time>) {
+fn you_lost(
+    mut state: ResMut<Stats>,
+    mut game_state: ResMut<State<GameState>>,
+    time: Res<SimulationTime>,
+) {
     if state.life == 0 {
         warn!("you lost!");
-        game_state.set(GameState::Lost).unwrap();
+        // the state changes once the frame is over, there may be other steps until then
+        let _ = game_state.set(GameState::Lost);
     }
     state.time.tick(time.delta());
 }
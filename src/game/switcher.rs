@@ -1,19 +1,23 @@
 use std::f32::consts::PI;
 
 use bevy::{
+    ecs::schedule::ShouldRun,
     prelude::{
         App, Color, Commands, DespawnRecursiveExt, DirectionalLight, Entity, Or, Quat, Query, Res,
         ResMut, State, SystemSet, Transform, Vec3, Visibility, With,
     },
-    time::{Time, Timer},
+    time::Timer,
 };
 use bevy_easings::{EaseFunction, EaseValue, Lerp};
 use interpolation::Ease;
 use tracing::info;
 
+use crate::GameState;
+
 use super::{
     terra::Plane,
     terrain_spawner::FilledLot,
+    timestep::{SimulationStage, SimulationTime},
     towers::Missile,
     zombies::{IdleZombie, Zombie},
     PlayingState,
@@ -25,11 +29,19 @@ impl bevy::app::Plugin for Plugin {
         app.add_system_set(
             SystemSet::on_enter(PlayingState::SwitchingPlane).with_system(change_plane),
         )
-        .add_system_set(SystemSet::on_update(PlayingState::SwitchingPlane).with_system(tick))
+        .add_system_set_to_stage(
+            SimulationStage,
+            SystemSet::new()
+                .with_run_criteria(switching)
+                .with_system(tick),
+        )
         .add_system_set(SystemSet::on_exit(PlayingState::SwitchingPlane).with_system(clear));
     }
 }
 
+/// Color of the light while on the Ethereal plane.
+pub(crate) const ETHEREAL_LIGHT: Color = Color::rgb(1.0, 0.7, 1.0);
+
 struct SwitchingTimer(Timer);
 
 #[allow(clippy::type_complexity)]
@@ -55,9 +67,24 @@ fn change_plane(
     info!("now on {:?} plane", *plane);
 }
 
+/// Run criteria for systems of the [`SimulationStage`] that only run while switching plane
+/// during a game.
+fn switching(
+    game_state: Res<State<GameState>>,
+    playing_state: Res<State<PlayingState>>,
+) -> ShouldRun {
+    if *game_state.current() == GameState::Playing
+        && *playing_state.current() == PlayingState::SwitchingPlane
+    {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
 fn tick(
     mut lots: Query<(&mut Transform, &FilledLot)>,
-    time: Res<Time>,
+    time: Res<SimulationTime>,
     mut timer: ResMut<SwitchingTimer>,
     mut playing_state: ResMut<State<PlayingState>>,
     plane: Res<Plane>,
@@ -77,7 +104,7 @@ fn tick(
     }
 
     let material = Color::WHITE;
-    let ethereal = Color::rgb(1.0, 0.7, 1.0);
+    let ethereal = ETHEREAL_LIGHT;
     light.single_mut().color = match *plane {
         Plane::Material => {
             EaseValue(ethereal)
@@ -1,28 +1,76 @@
-use bevy::prelude::Plugin;
-use rand::{thread_rng, Rng};
+use bevy::prelude::{Commands, Plugin, Res, SystemSet};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::GameState;
 
 pub(crate) struct TerraPlugin;
 
+/// Seed of the current run, driving terrain generation, scenery placement and zombie spawns.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RunSeed(pub(crate) u64);
+
+impl RunSeed {
+    pub(crate) fn random() -> Self {
+        Self(thread_rng().gen())
+    }
+}
+
 #[derive(Clone, Copy)]
 pub(crate) struct TerraNoises {
+    pub(crate) seed: u64,
     pub(crate) material_seed: u32,
+    pub(crate) ethereal_seed: u32,
 }
 
 impl TerraNoises {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn from_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
         Self {
-            material_seed: thread_rng().gen(),
+            seed,
+            material_seed: rng.gen(),
+            ethereal_seed: rng.gen(),
         }
     }
+
+    /// Random generator for anything identified by its `coordinates`, so that it doesn't depend
+    /// on the order in which lots are generated.
+    pub(crate) fn rng_for(&self, coordinates: &[i32]) -> StdRng {
+        // mixed by hand rather than with a `Hasher`, whose output can change between Rust
+        // versions or targets
+        let seed = coordinates
+            .iter()
+            .fold(splitmix64(self.seed), |state, coordinate| {
+                splitmix64(state ^ *coordinate as u32 as u64)
+            });
+        StdRng::seed_from_u64(seed)
+    }
+}
+
+/// Mixing function of the SplitMix64 generator, spreading any change of its input over its
+/// whole output.
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
 }
 
 impl Plugin for TerraPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.insert_resource(TerraNoises::new());
+        let seed = *app.world.get_resource_or_insert_with(RunSeed::random);
+        app.insert_resource(TerraNoises::from_seed(seed.0))
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup));
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+fn setup(mut commands: Commands, seed: Res<RunSeed>) {
+    info!("starting run with seed {}", seed.0);
+    commands.insert_resource(TerraNoises::from_seed(seed.0));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) enum Plane {
     Material,
     Ethereal,
@@ -36,3 +84,30 @@ impl Plane {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::{splitmix64, TerraNoises};
+
+    #[test]
+    fn splitmix_is_stable() {
+        // first outputs of the reference SplitMix64 generator seeded with 0
+        assert_eq!(splitmix64(0), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(splitmix64(0x9e37_79b9_7f4a_7c15), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn rng_depends_on_seed_and_coordinates() {
+        let noises = TerraNoises::from_seed(42);
+        let value =
+            |noises: &TerraNoises, coordinates: &[i32]| noises.rng_for(coordinates).gen::<u64>();
+        assert_eq!(value(&noises, &[1, 2]), value(&noises, &[1, 2]));
+        assert_ne!(value(&noises, &[1, 2]), value(&noises, &[2, 1]));
+        assert_ne!(
+            value(&noises, &[1, 2]),
+            value(&TerraNoises::from_seed(43), &[1, 2])
+        );
+    }
+}
@@ -3,35 +3,196 @@ use std::f32::consts::{FRAC_PI_4, PI};
 use bevy::{
     ecs::component::SparseStorage,
     prelude::*,
+    render::mesh::VertexAttributeValues,
     tasks::AsyncComputeTaskPool,
     utils::{Entry, HashMap},
 };
 use bevy_mod_raycast::{Intersection, RayCastMesh, RayCastMethod, RayCastSource, SimplifiedMesh};
 use crossbeam_channel::{Receiver, Sender};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     assets::{BuildingAssets, SceneryAssets},
     game::terra::{Plane, TerraNoises},
     game::{
-        heightmap::{HeightMap, LOW_DEF},
+        flow_field::FlowFields,
+        heightmap::{HeightMap, Terrain, TerrainConfig},
         stats::GameTag,
+        towers::{TowerKind, TowerModel},
     },
     GameState,
 };
 
+/// Navigation meshes for each plane.
 #[derive(Default, Clone)]
 pub(crate) struct Pathfinding {
-    pub(crate) mesh: polyanya::Mesh,
+    material: NavMesh,
+    ethereal: NavMesh,
 }
 
 impl Pathfinding {
-    pub(crate) fn cut_polygon_out(&mut self, coords: (IVec2, IVec2)) {
+    pub(crate) fn from_map(map: &Map, config: &TerrainConfig) -> Self {
+        Self {
+            material: NavMesh::from_map(map, Plane::Material, config),
+            ethereal: NavMesh::from_map(map, Plane::Ethereal, config),
+        }
+    }
+
+    pub(crate) fn plane(&self, plane: Plane) -> &NavMesh {
+        match plane {
+            Plane::Material => &self.material,
+            Plane::Ethereal => &self.ethereal,
+        }
+    }
+
+    pub(crate) fn plane_mut(&mut self, plane: Plane) -> &mut NavMesh {
+        match plane {
+            Plane::Material => &mut self.material,
+            Plane::Ethereal => &mut self.ethereal,
+        }
+    }
+
+    /// Whether a zombie at `from` can reach the crystal on both planes.
+    pub(crate) fn reaches_crystal(&self, from: Vec2) -> bool {
+        self.material.mesh.path(from, Vec2::ZERO).complete
+            && self.ethereal.mesh.path(from, Vec2::ZERO).complete
+    }
+
+    /// Remove the lot at `coords` from both meshes, as a tower blocks it on both planes.
+    pub(crate) fn cut_polygon_out(&mut self, coords: (IVec2, IVec2), config: &TerrainConfig) {
+        self.material.cut_polygon_out(coords, config);
+        self.ethereal.cut_polygon_out(coords, config);
+    }
+
+    /// Update both meshes after the lot at `coords` changed in `map`.
+    pub(crate) fn refresh_lot(
+        &mut self,
+        coords: (IVec2, IVec2),
+        map: &Map,
+        config: &TerrainConfig,
+    ) -> [NavmeshChanged; 2] {
+        self.material.refresh_lot(coords, map, config);
+        self.ethereal.refresh_lot(coords, map, config);
+        [
+            NavmeshChanged::Lots(Plane::Material, vec![coords]),
+            NavmeshChanged::Lots(Plane::Ethereal, vec![coords]),
+        ]
+    }
+
+    pub(crate) fn merge_tile(
+        &mut self,
+        position: IVec2,
+        map: &Map,
+        config: &TerrainConfig,
+    ) -> [NavmeshChanged; 2] {
+        [
+            self.material.merge_tile(position, map, config),
+            self.ethereal.merge_tile(position, map, config),
+        ]
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct NavMesh {
+    pub(crate) mesh: polyanya::Mesh,
+    plane: Plane,
+    half_width: isize,
+    half_height: isize,
+}
+
+impl Default for NavMesh {
+    fn default() -> Self {
+        Self {
+            mesh: Default::default(),
+            plane: Plane::Material,
+            half_width: 0,
+            half_height: 0,
+        }
+    }
+}
+
+impl NavMesh {
+    /// Build a navigation mesh for `plane` covering every lot generated so far.
+    pub(crate) fn from_map(map: &Map, plane: Plane, config: &TerrainConfig) -> Self {
+        let (half_width, half_height) = map.half_extent();
+        let half_width = half_width.max(BORDER as isize + MAP_DELTA);
+        let half_height = half_height.max(BORDER as isize + MAP_DELTA);
+        Self {
+            mesh: new_mesh_from_map(map, plane, half_width, half_height, config),
+            plane,
+            half_width,
+            half_height,
+        }
+    }
+
+    pub(crate) fn covers(&self, map: IVec2) -> bool {
+        (-self.half_width..=self.half_width).contains(&(map.x as isize))
+            && (-self.half_height..=self.half_height).contains(&(map.y as isize))
+    }
+
+    /// Update the mesh in place after the lot at `coords` changed in `map`.
+    pub(crate) fn refresh_lot(
+        &mut self,
+        coords: (IVec2, IVec2),
+        map: &Map,
+        config: &TerrainConfig,
+    ) {
+        if !self.covers(coords.0) {
+            return;
+        }
+        let id = coords_to_polygon_id(coords, self.half_width, self.half_height, config) as usize;
+        let row = (self.half_width as usize * 2 + 1) * config.lots_per_tile as usize;
+        // the polygon and the vertices at each of its corners
+        for corner in [id, id + 1, id + row, id + 1 + row] {
+            if corner < self.mesh.polygons.len() {
+                build_polygon(
+                    &mut self.mesh,
+                    polygon_id_to_coords(corner, self.half_width, self.half_height, config),
+                    map,
+                    self.plane,
+                    self.half_width,
+                    self.half_height,
+                    config,
+                );
+            }
+        }
+    }
+
+    /// Take a newly generated tile into account, rebuilding the mesh only if it's not covered yet.
+    pub(crate) fn merge_tile(
+        &mut self,
+        position: IVec2,
+        map: &Map,
+        config: &TerrainConfig,
+    ) -> NavmeshChanged {
+        if !self.covers(position) {
+            *self = Self::from_map(map, self.plane, config);
+            return NavmeshChanged::Rebuilt(self.plane);
+        }
+        let obstacles = map
+            .lots
+            .get(&(position, self.plane))
+            .map(|lot| {
+                lot.iter()
+                    .filter(|(_, occupying)| !occupying.is_path_free())
+                    .map(|(lot, _)| (position, *lot))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for coords in &obstacles {
+            self.refresh_lot(*coords, map, config);
+        }
+        NavmeshChanged::Lots(self.plane, obstacles)
+    }
+
+    pub(crate) fn cut_polygon_out(&mut self, coords: (IVec2, IVec2), config: &TerrainConfig) {
         Self::inner_cut_polygon_out(
             &mut self.mesh,
             coords,
-            BORDER as isize + MAP_DELTA,
-            BORDER as isize + MAP_DELTA,
+            self.half_width,
+            self.half_height,
+            config,
         );
     }
 
@@ -40,8 +201,9 @@ impl Pathfinding {
         coords: (IVec2, IVec2),
         half_width: isize,
         half_height: isize,
+        config: &TerrainConfig,
     ) {
-        let id = coords_to_polygon_id(coords, half_width, half_height);
+        let id = coords_to_polygon_id(coords, half_width, half_height, config);
         let poly = mesh.polygons.get_mut(id as usize).unwrap();
         for v in &poly.vertices {
             let v = mesh.vertices.get_mut(*v).unwrap();
@@ -57,7 +219,19 @@ impl Pathfinding {
     }
 }
 
-use super::{nests::ZombieNest, stats::Stats, PlayingState};
+use super::{
+    balance::Balance, nests::ZombieNest, save::SavedTiles, stats::Stats, timestep::SimulationStage,
+    PlayingState,
+};
+
+/// Sent when the navigation mesh changes, so that zombies going through the change can find a new
+/// path.
+pub(crate) enum NavmeshChanged {
+    /// Only those lots changed on the plane.
+    Lots(Plane, Vec<(IVec2, IVec2)>),
+    /// The whole mesh of the plane was rebuilt.
+    Rebuilt(Plane),
+}
 
 const BORDER: f32 = 2.0;
 const MAP_DELTA: isize = 3;
@@ -109,10 +283,14 @@ struct MyChannel(Sender<InTransitLot>, Receiver<InTransitLot>);
 impl Plugin for TerrainSpawnerPlugin {
     fn build(&self, app: &mut App) {
         let (tx, rx) = crossbeam_channel::bounded(CHANNEL_SIZE);
+        let low_def = app
+            .world
+            .get_resource_or_insert_with(TerrainConfig::default)
+            .lots_per_tile;
 
         let mut crystal = HashMap::new();
         crystal.insert(
-            IVec2::new(LOW_DEF as i32 / 2, LOW_DEF as i32 / 2),
+            IVec2::new(low_def as i32 / 2, low_def as i32 / 2),
             Occupying::Crystal,
         );
         let mut map = Map::default();
@@ -122,20 +300,36 @@ impl Plugin for TerrainSpawnerPlugin {
             .insert((IVec2::new(0, 0), Plane::Ethereal), crystal);
 
         app.insert_resource(MyChannel(tx, rx))
-            .init_resource::<VisibleLots>()
+            // read by the simulation, kept until it ran instead of for a couple of frames
+            .init_resource::<Events<NavmeshChanged>>()
+            .add_system_to_stage(
+                SimulationStage,
+                Events::<NavmeshChanged>::update_system
+                    .exclusive_system()
+                    .at_end(),
+            )
             .init_resource::<CursorPosition>()
             .init_resource::<Pathfinding>()
             .init_resource::<MeshCache>()
             .insert_resource(map)
             .insert_resource(Plane::Material)
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(fill_empty_lots));
+    }
+}
+
+/// Picks the lots to generate from what the camera sees, and the lot under the cursor.
+pub(crate) struct TerrainCameraPlugin;
+
+impl Plugin for TerrainCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VisibleLots>()
             .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup_camera))
             .add_system_set(
                 SystemSet::on_update(GameState::Playing)
                     .with_system(move_camera)
-                    .with_system(fill_empty_lots)
                     .with_system(refresh_visible_lots.after(fill_empty_lots))
-                    .with_system(intersection)
-                    .with_system(update_pathfinding),
+                    .with_system(intersection),
             );
     }
 }
@@ -151,13 +345,27 @@ fn setup_camera(mut commands: Commands, mut camera: Query<&mut Transform, With<C
     let mut transform = camera.single_mut();
     *transform = Transform::from_xyz(0.0, 5.0, -0.5).looking_at(Vec3::new(0.0, 0.0, 0.0), Vec3::Y);
     commands.insert_resource(VisibleLots::default());
+}
+
+fn setup(
+    mut commands: Commands,
+    config: Res<TerrainConfig>,
+    mut navmesh_changes: EventWriter<NavmeshChanged>,
+) {
     commands.insert_resource(CursorPosition::default());
-    commands.insert_resource(Pathfinding::default());
     commands.insert_resource(MeshCache::default());
+    // don't keep the flow fields of the previous run, they're computed again from the new map
+    commands.insert_resource(FlowFields::default());
+    navmesh_changes.send_batch(
+        [Plane::Material, Plane::Ethereal]
+            .into_iter()
+            .map(NavmeshChanged::Rebuilt),
+    );
 
+    let low_def = config.lots_per_tile;
     let mut crystal = HashMap::new();
     crystal.insert(
-        IVec2::new(LOW_DEF as i32 / 2, LOW_DEF as i32 / 2),
+        IVec2::new(low_def as i32 / 2, low_def as i32 / 2),
         Occupying::Crystal,
     );
     let mut map = Map::default();
@@ -165,17 +373,14 @@ fn setup_camera(mut commands: Commands, mut camera: Query<&mut Transform, With<C
         .insert((IVec2::new(0, 0), Plane::Material), crystal.clone());
     map.lots
         .insert((IVec2::new(0, 0), Plane::Ethereal), crystal);
+    commands.insert_resource(Pathfinding::from_map(&map, &config));
     commands.insert_resource(map);
     commands.insert_resource(Plane::Material);
-    commands.insert_resource(TerraNoises::new())
 }
 
 struct InTransitLot {
-    mesh: Mesh,
-    material_color: Image,
-    ethereal_color: Image,
-    metallic_roughness: Image,
-    mountain_map: Vec<IVec2>,
+    material: Terrain,
+    ethereal: Terrain,
     x: i32,
     z: i32,
 }
@@ -184,29 +389,32 @@ struct HandledLot {
     color: Handle<StandardMaterial>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum Occupying {
     Crystal,
     Tree,
     Bench(f32),
     Rock(f32),
     Mountain,
-    Tower,
-    Block,
+    Tower(TowerKind),
+    /// Lot of a tower on the other plane, with what was there before it was built.
+    Block(Option<Box<Occupying>>),
     Coffin(f32),
 }
 
 impl Occupying {
     pub(crate) fn is_free(&self) -> bool {
         match self {
-            Self::Crystal | Self::Mountain | Self::Tower | Self::Block | Self::Coffin(_) => false,
+            Self::Crystal | Self::Mountain | Self::Tower(_) | Self::Block(_) | Self::Coffin(_) => {
+                false
+            }
             Self::Tree | Self::Bench(_) | Self::Rock(_) => true,
         }
     }
     #[inline(always)]
     pub(crate) fn is_path_free(&self) -> bool {
         match self {
-            Self::Mountain | Self::Tower | Self::Block => false,
+            Self::Mountain | Self::Tower(_) | Self::Block(_) => false,
             Self::Crystal | Self::Tree | Self::Bench(_) | Self::Rock(_) | Self::Coffin(_) => true,
         }
     }
@@ -217,8 +425,65 @@ pub(crate) struct Map {
     pub(crate) lots: HashMap<(IVec2, Plane), HashMap<IVec2, Occupying>>,
 }
 
+impl Map {
+    /// Half size of the smallest rectangle centered on the crystal that contains every lot.
+    pub(crate) fn half_extent(&self) -> (isize, isize) {
+        self.lots
+            .keys()
+            .fold((0, 0), |(width, height), (position, _)| {
+                (
+                    width.max(position.x.abs() as isize),
+                    height.max(position.y.abs() as isize),
+                )
+            })
+    }
+
+    /// Whether a tower can be built on the lot at `coords`, which it blocks on both planes.
+    pub(crate) fn is_buildable(&self, coords: (IVec2, IVec2)) -> bool {
+        [Plane::Material, Plane::Ethereal].iter().all(|plane| {
+            self.lots
+                .get(&(coords.0, *plane))
+                .and_then(|lots| lots.get(&coords.1))
+                .map(|occupying| occupying.is_free())
+                .unwrap_or(true)
+        })
+    }
+}
+
+/// Height of the terrain meshes above their lot.
+const TERRAIN_ELEVATION: f32 = 0.04;
+
 #[derive(Default)]
-struct MeshCache(HashMap<(IVec2, Plane), HandledLot>);
+pub(crate) struct MeshCache(HashMap<(IVec2, Plane), HandledLot>);
+
+impl MeshCache {
+    /// Height of the ground of `plane` at `position`, read from the terrain meshes. `None` while
+    /// its tile isn't generated.
+    pub(crate) fn height_at(
+        &self,
+        position: Vec2,
+        plane: Plane,
+        meshes: &Assets<Mesh>,
+        config: &TerrainConfig,
+    ) -> Option<f32> {
+        let tile = IVec2::new(position.x.round() as i32, position.y.round() as i32);
+        let mesh = meshes.get(&self.0.get(&(tile, plane))?.mesh)?;
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+            VertexAttributeValues::Float32x3(positions) => positions,
+            _ => return None,
+        };
+        // vertices are a grid of `mesh_detail + 1` rows along x, each of `mesh_detail + 1` along z
+        let detail = config.mesh_detail as usize;
+        let x = ((position.x - tile.x as f32 + 0.5) * detail as f32).clamp(0.0, detail as f32);
+        let z = ((position.y - tile.y as f32 + 0.5) * detail as f32).clamp(0.0, detail as f32);
+        let (i, j) = ((x as usize).min(detail - 1), (z as usize).min(detail - 1));
+        let (dx, dz) = (x - i as f32, z - j as f32);
+        let height = |i: usize, j: usize| positions.get(i * (detail + 1) + j).map(|p| p[1]);
+        let near = height(i, j)? * (1.0 - dx) + height(i + 1, j)? * dx;
+        let far = height(i, j + 1)? * (1.0 - dx) + height(i + 1, j + 1)? * dx;
+        Some(near * (1.0 - dz) + far * dz + TERRAIN_ELEVATION)
+    }
+}
 
 #[allow(clippy::type_complexity)]
 fn fill_empty_lots(
@@ -230,7 +495,7 @@ fn fill_empty_lots(
         ResMut<Assets<StandardMaterial>>,
     ),
     mut mesh_cache: ResMut<MeshCache>,
-    noises: Res<TerraNoises>,
+    (noises, balance): (Res<TerraNoises>, Res<Balance>),
     channel: Res<MyChannel>,
     mut in_transit: Local<usize>,
     plane: Res<Plane>,
@@ -238,8 +503,12 @@ fn fill_empty_lots(
     mut map: ResMut<Map>,
     building_assets: Res<BuildingAssets>,
     scenery_assets: Res<SceneryAssets>,
-    pathfinding: Res<Pathfinding>,
+    mut pathfinding: ResMut<Pathfinding>,
+    config: Res<TerrainConfig>,
+    mut navmesh_changes: EventWriter<NavmeshChanged>,
+    mut saved_tiles: ResMut<SavedTiles>,
 ) {
+    let low_def = config.lots_per_tile;
     for (entity, mut position, mut transform) in lots.iter_mut() {
         if let Some(mesh) = mesh_cache
             .0
@@ -249,7 +518,7 @@ fn fill_empty_lots(
                 commands
                     .entity(entity)
                     .with_children(|lot| {
-                        let delta = 0.04;
+                        let delta = TERRAIN_ELEVATION;
                         lot.spawn_bundle(PbrBundle {
                             mesh: mesh.mesh.clone_weak(),
                             material: mesh.color.clone_weak(),
@@ -266,20 +535,25 @@ fn fill_empty_lots(
                         if let Some(building_lot) =
                             map.lots.get(&(IVec2::new(position.x, position.z), *plane))
                         {
-                            let mut rng = rand::thread_rng();
                             for building in building_lot {
+                                let mut rng = noises.rng_for(&[
+                                    position.x,
+                                    position.z,
+                                    building.0.x,
+                                    building.0.y,
+                                ]);
                                 match building.1 {
                                     Occupying::Crystal => {
                                         lot.spawn_bundle(SceneBundle {
                                             scene: building_assets.crystal.clone_weak(),
                                             transform: Transform {
-                                                scale: Vec3::splat(1.0 / LOW_DEF as f32),
+                                                scale: Vec3::splat(1.0 / low_def as f32),
                                                 translation: Vec3::new(
-                                                    -(building.0.x - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    -(building.0.x - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                     delta,
-                                                    (building.0.y - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    (building.0.y - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                 ),
                                                 ..default()
                                             },
@@ -295,14 +569,14 @@ fn fill_empty_lots(
                                             },
                                             transform: Transform {
                                                 scale: Vec3::splat(
-                                                    1.0 / LOW_DEF as f32 * rng.gen_range(0.7..0.9),
+                                                    1.0 / low_def as f32 * rng.gen_range(0.7..0.9),
                                                 ),
                                                 translation: Vec3::new(
-                                                    -(building.0.x - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    -(building.0.x - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                     delta,
-                                                    (building.0.y - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    (building.0.y - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                 ),
                                                 rotation: Quat::from_rotation_y(rng.gen_range(
                                                     (FRAC_PI_4 * 9.0 / 10.0)
@@ -320,13 +594,13 @@ fn fill_empty_lots(
                                                 scenery_assets.bench_damaged.clone_weak()
                                             },
                                             transform: Transform {
-                                                scale: Vec3::splat(0.5 / LOW_DEF as f32),
+                                                scale: Vec3::splat(0.5 / low_def as f32),
                                                 translation: Vec3::new(
-                                                    -(building.0.x - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    -(building.0.x - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                     delta,
-                                                    (building.0.y - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    (building.0.y - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                 ),
                                                 rotation: Quat::from_rotation_y(*a),
                                             },
@@ -338,57 +612,55 @@ fn fill_empty_lots(
                                             scene: scenery_assets.rock.clone_weak(),
                                             transform: Transform {
                                                 scale: Vec3::splat(
-                                                    1.0 / LOW_DEF as f32 * rng.gen_range(0.7..0.9),
+                                                    1.0 / low_def as f32 * rng.gen_range(0.7..0.9),
                                                 ),
                                                 translation: Vec3::new(
-                                                    -(building.0.x - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    -(building.0.x - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                     delta,
-                                                    (building.0.y - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    (building.0.y - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                 ),
                                                 rotation: Quat::from_rotation_y(*a),
                                             },
                                             ..default()
                                         });
                                     }
-                                    Occupying::Tower => {
+                                    Occupying::Tower(kind) => {
                                         lot.spawn_bundle(SceneBundle {
-                                            scene: if *plane == Plane::Material {
-                                                building_assets.material_tower.clone_weak()
-                                            } else {
-                                                building_assets.ethereal_tower.clone_weak()
-                                            },
+                                            scene: kind.scene(*plane, &building_assets),
                                             transform: Transform {
-                                                scale: Vec3::splat(TOWER_SCALE / LOW_DEF as f32),
+                                                scale: kind.scale() * TOWER_SCALE / low_def as f32,
                                                 translation: Vec3::new(
-                                                    -(building.0.x - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    -(building.0.x - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                     delta,
-                                                    (building.0.y - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    (building.0.y - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                 ),
                                                 ..default()
                                             },
                                             ..default()
-                                        });
+                                        })
+                                        .insert(TowerModel { lot: *building.0 });
                                     }
-                                    Occupying::Block => {
+                                    Occupying::Block(_) => {
                                         lot.spawn_bundle(SceneBundle {
                                             scene: building_assets.block.clone_weak(),
                                             transform: Transform {
-                                                scale: Vec3::splat(1.0 / LOW_DEF as f32),
+                                                scale: Vec3::splat(1.0 / low_def as f32),
                                                 translation: Vec3::new(
-                                                    -(building.0.x - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    -(building.0.x - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                     delta,
-                                                    (building.0.y - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    (building.0.y - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                 ),
                                                 ..default()
                                             },
                                             ..default()
-                                        });
+                                        })
+                                        .insert(TowerModel { lot: *building.0 });
                                     }
                                     Occupying::Mountain => (),
                                     Occupying::Coffin(a) => {
@@ -399,13 +671,13 @@ fn fill_empty_lots(
                                                 building_assets.coffin_old.clone_weak()
                                             },
                                             transform: Transform {
-                                                scale: Vec3::splat(1.0 / LOW_DEF as f32),
+                                                scale: Vec3::splat(1.0 / low_def as f32),
                                                 translation: Vec3::new(
-                                                    -(building.0.x - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    -(building.0.x - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                     delta,
-                                                    (building.0.y - LOW_DEF as i32 / 2) as f32
-                                                        / LOW_DEF as f32,
+                                                    (building.0.y - low_def as i32 / 2) as f32
+                                                        / low_def as f32,
                                                 ),
                                                 rotation: Quat::from_rotation_y(*a),
                                             },
@@ -432,21 +704,22 @@ fn fill_empty_lots(
             let pos_x = position.x as f32;
             let pos_y = position.z as f32;
             let noises = *noises;
+            let config = *config;
             let tx = channel.0.clone();
-            let plane = *plane;
             AsyncComputeTaskPool::get()
                 .spawn(async move {
-                    let heightmap = HeightMap::build_heightmap(pos_x, pos_y, plane, noises);
-                    let terrain = heightmap.into_mesh_and_texture();
+                    let material =
+                        HeightMap::build_heightmap(pos_x, pos_y, Plane::Material, noises, config)
+                            .into_mesh_and_texture();
+                    let ethereal =
+                        HeightMap::build_heightmap(pos_x, pos_y, Plane::Ethereal, noises, config)
+                            .into_mesh_and_texture();
 
                     tx.send(InTransitLot {
-                        mesh: terrain.mesh,
-                        material_color: terrain.material_color,
-                        ethereal_color: terrain.ethereal_color,
-                        metallic_roughness: terrain.metallic_roughness,
+                        material,
+                        ethereal,
                         x: pos_x as i32,
                         z: pos_y as i32,
-                        mountain_map: terrain.simplified_map,
                     })
                     .unwrap();
                 })
@@ -455,93 +728,89 @@ fn fill_empty_lots(
             *in_transit += 1;
         }
         for lot in channel.1.try_iter() {
-            let material_lot = map
-                .lots
-                .entry((IVec2::new(lot.x, lot.z), Plane::Material))
-                .or_default();
-            for mountain in &lot.mountain_map {
-                material_lot.insert(
-                    IVec2::new(LOW_DEF as i32 - mountain.x - 1, mountain.y),
-                    Occupying::Mountain,
-                );
-            }
-            let ethereal_lot = map
-                .lots
-                .entry((IVec2::new(lot.x, lot.z), Plane::Ethereal))
-                .or_default();
-            for mountain in lot.mountain_map {
-                ethereal_lot.insert(
-                    IVec2::new(LOW_DEF as i32 - mountain.x - 1, mountain.y),
-                    Occupying::Mountain,
+            *in_transit -= 1;
+            let saved_tile = saved_tiles.0.remove(&IVec2::new(lot.x, lot.z));
+            for (lot_plane, terrain) in [
+                (Plane::Material, lot.material),
+                (Plane::Ethereal, lot.ethereal),
+            ] {
+                let plane_lot = map
+                    .lots
+                    .entry((IVec2::new(lot.x, lot.z), lot_plane))
+                    .or_default();
+                for mountain in terrain.simplified_map {
+                    plane_lot.insert(
+                        IVec2::new(low_def as i32 - mountain.x - 1, mountain.y),
+                        Occupying::Mountain,
+                    );
+                }
+                if let Some(saved_tile) = &saved_tile {
+                    plane_lot.extend(saved_tile.lots(lot_plane));
+                }
+
+                mesh_cache.0.insert(
+                    (IVec2::new(lot.x, lot.z), lot_plane),
+                    HandledLot {
+                        mesh: meshes.add(terrain.mesh),
+                        color: materials.add(StandardMaterial {
+                            base_color: bevy::render::color::Color::WHITE,
+                            base_color_texture: Some(textures.add(terrain.color)),
+                            perceptual_roughness: 1.0,
+                            metallic: 1.0,
+                            metallic_roughness_texture: Some(
+                                textures.add(terrain.metallic_roughness),
+                            ),
+                            ..Default::default()
+                        }),
+                    },
                 );
             }
-
-            let mesh_handle = meshes.add(lot.mesh);
-            let mr_texture = textures.add(lot.metallic_roughness);
-            let material_handled_lot = HandledLot {
-                mesh: mesh_handle.clone(),
-                color: materials.add(StandardMaterial {
-                    base_color: bevy::render::color::Color::WHITE,
-                    base_color_texture: Some(textures.add(lot.material_color)),
-                    perceptual_roughness: 1.0,
-                    metallic: 1.0,
-                    metallic_roughness_texture: Some(mr_texture.clone()),
-                    ..Default::default()
-                }),
-            };
-            let ethereal_handled_lot = HandledLot {
-                mesh: mesh_handle,
-                color: materials.add(StandardMaterial {
-                    base_color: bevy::render::color::Color::WHITE,
-                    base_color_texture: Some(textures.add(lot.ethereal_color)),
-                    perceptual_roughness: 1.0,
-                    metallic: 1.0,
-                    metallic_roughness_texture: Some(mr_texture),
-                    ..Default::default()
-                }),
-            };
-            *in_transit -= 1;
-            mesh_cache.0.insert(
-                (IVec2::new(lot.x, lot.z), Plane::Material),
-                material_handled_lot,
-            );
-            mesh_cache.0.insert(
-                (IVec2::new(lot.x, lot.z), Plane::Ethereal),
-                ethereal_handled_lot,
+            // update the mesh before checking that nests in this lot can reach the crystal
+            navmesh_changes.send_batch(
+                pathfinding
+                    .merge_tile(IVec2::new(lot.x, lot.z), &map, &config)
+                    .into_iter(),
             );
-            let mut rng = rand::thread_rng();
-            for i in 0..LOW_DEF {
-                for j in 0..LOW_DEF {
+            if let Some(saved_tile) = saved_tile {
+                for nest in saved_tile.nests() {
+                    commands.spawn().insert_bundle((nest, GameTag));
+                }
+                continue;
+            }
+            let mut rng = noises.rng_for(&[lot.x, lot.z]);
+            for i in 0..low_def {
+                for j in 0..low_def {
                     if rng.gen_bool(
-                        Vec2::new(lot.x as f32, lot.z as f32).distance_squared(Vec2::ZERO) as f64
-                            / 3000.0,
+                        (Vec2::new(lot.x as f32, lot.z as f32).distance_squared(Vec2::ZERO) as f64
+                            / balance.scenery.nest_distance)
+                            .min(1.0),
                     ) {
-                        let world = map_to_world((
-                            IVec2::new(lot.x, lot.z),
-                            IVec2::new(i as i32, j as i32),
-                        ));
-                        if pathfinding.mesh.path(world, Vec2::ZERO).complete {
-                            let a = rng.gen_range(0.0..(2.0 * PI));
-                            if map
-                                .lots
-                                .get_mut(&(IVec2::new(lot.x, lot.z), Plane::Material))
-                                .unwrap()
-                                .try_insert(IVec2::new(i as i32, j as i32), Occupying::Coffin(a))
-                                .is_ok()
-                            {
-                                commands.spawn().insert(ZombieNest {
+                        let world = map_to_world(
+                            (IVec2::new(lot.x, lot.z), IVec2::new(i as i32, j as i32)),
+                            &config,
+                        );
+                        let a = rng.gen_range(0.0..(2.0 * PI));
+                        let is_free_on_both_planes =
+                            [Plane::Material, Plane::Ethereal].iter().all(|lot_plane| {
+                                !map.lots[&(IVec2::new(lot.x, lot.z), *lot_plane)]
+                                    .contains_key(&IVec2::new(i as i32, j as i32))
+                            });
+                        if is_free_on_both_planes && pathfinding.reaches_crystal(world) {
+                            for lot_plane in [Plane::Material, Plane::Ethereal] {
+                                map.lots
+                                    .get_mut(&(IVec2::new(lot.x, lot.z), lot_plane))
+                                    .unwrap()
+                                    .insert(IVec2::new(i as i32, j as i32), Occupying::Coffin(a));
+                            }
+                            commands.spawn().insert_bundle((
+                                ZombieNest {
                                     map: IVec2::new(lot.x, lot.z),
                                     lot: IVec2::new(i as i32, j as i32),
-                                    timer: Timer::from_seconds(6.0, true),
-                                });
-                            }
-                            let _ = map
-                                .lots
-                                .get_mut(&(IVec2::new(lot.x, lot.z), Plane::Ethereal))
-                                .unwrap()
-                                .try_insert(IVec2::new(i as i32, j as i32), Occupying::Coffin(a));
+                                },
+                                GameTag,
+                            ));
                         }
-                    } else if rng.gen_bool(0.01) {
+                    } else if rng.gen_bool(balance.scenery.tree) {
                         let _ = map
                             .lots
                             .get_mut(&(IVec2::new(lot.x, lot.z), Plane::Material))
@@ -552,7 +821,7 @@ fn fill_empty_lots(
                             .get_mut(&(IVec2::new(lot.x, lot.z), Plane::Ethereal))
                             .unwrap()
                             .try_insert(IVec2::new(i as i32, j as i32), Occupying::Tree);
-                    } else if rng.gen_bool(0.005) {
+                    } else if rng.gen_bool(balance.scenery.bench) {
                         let a = rng.gen_range(0.0..(2.0 * PI));
                         let _ = map
                             .lots
@@ -564,7 +833,7 @@ fn fill_empty_lots(
                             .get_mut(&(IVec2::new(lot.x, lot.z), Plane::Ethereal))
                             .unwrap()
                             .try_insert(IVec2::new(i as i32, j as i32), Occupying::Bench(a));
-                    } else if rng.gen_bool(0.005) {
+                    } else if rng.gen_bool(balance.scenery.rock) {
                         let a = rng.gen_range(0.0..(2.0 * PI));
                         let _ = map
                             .lots
@@ -707,21 +976,23 @@ fn move_camera(
 
 pub(crate) struct RaycastSet;
 
-pub(crate) fn world_to_map(world: Vec2) -> (IVec2, IVec2) {
+pub(crate) fn world_to_map(world: Vec2, config: &TerrainConfig) -> (IVec2, IVec2) {
+    let low_def = config.lots_per_tile;
     (
         IVec2::new(world.x.round() as i32, world.y.round() as i32),
         IVec2::new(
-            ((1.0 - (world.x - world.x.round() + 0.5)) * LOW_DEF as f32) as i32,
-            ((world.y - world.y.round() + 0.5) * LOW_DEF as f32) as i32,
+            ((1.0 - (world.x - world.x.round() + 0.5)) * low_def as f32) as i32,
+            ((world.y - world.y.round() + 0.5) * low_def as f32) as i32,
         ),
     )
 }
 
 #[inline(always)]
-pub(crate) fn map_to_world(map: (IVec2, IVec2)) -> Vec2 {
+pub(crate) fn map_to_world(map: (IVec2, IVec2), config: &TerrainConfig) -> Vec2 {
+    let low_def = config.lots_per_tile;
     Vec2::new(
-        map.0.x as f32 - (map.1.x as f32 + 0.5) / LOW_DEF as f32 + 0.5,
-        map.0.y as f32 + (map.1.y as f32 + 0.5) / LOW_DEF as f32 - 0.5,
+        map.0.x as f32 - (map.1.x as f32 + 0.5) / low_def as f32 + 0.5,
+        map.0.y as f32 + (map.1.y as f32 + 0.5) / low_def as f32 - 0.5,
     )
 }
 
@@ -730,13 +1001,14 @@ fn intersection(
     mut cursor: EventReader<CursorMoved>,
     mut pick_source: Query<&mut RayCastSource<RaycastSet>>,
     mut cursor_position: ResMut<CursorPosition>,
+    config: Res<TerrainConfig>,
 ) {
     for intersection in &query {
         if let Some(position) = intersection.position() {
-            let position = world_to_map(Vec2::new(position.x, position.z));
+            let position = world_to_map(Vec2::new(position.x, position.z), &config);
             cursor_position.map = position.0;
             cursor_position.lot = position.1;
-            let position = map_to_world(position);
+            let position = map_to_world(position, &config);
             cursor_position.world = Vec3::new(position.x, 0.05, position.y);
         }
     }
@@ -748,24 +1020,14 @@ fn intersection(
     pick_source.single_mut().cast_method = RayCastMethod::Screenspace(cursor_position);
 }
 
-fn update_pathfinding(map: Res<Map>, mut pathfinding: ResMut<Pathfinding>) {
-    if map.is_changed() {
-        info!("refreshing pathfinding mesh");
-        pathfinding.mesh = new_mesh_from_map(
-            &map,
-            BORDER as isize + MAP_DELTA,
-            BORDER as isize + MAP_DELTA,
-            LOW_DEF as usize,
-        );
-    }
-}
-
 fn new_mesh_from_map(
     map: &Map,
+    plane: Plane,
     half_width: isize,
     half_height: isize,
-    def: usize,
+    config: &TerrainConfig,
 ) -> polyanya::Mesh {
+    let def = config.lots_per_tile as usize;
     let count = (half_width * 2 + 1) * (half_height * 2 + 1) * (def as isize).pow(2);
     let mut mesh = polyanya::Mesh {
         vertices: vec![
@@ -784,71 +1046,15 @@ fn new_mesh_from_map(
             for il in 0..def as i32 {
                 for jl in 0..def as i32 {
                     let coords = (IVec2::new(im as i32, jm as i32), IVec2::new(il, jl));
-                    let id = coords_to_polygon_id(coords, half_width, half_height) as isize;
-                    let top_right = is_obstacle(coords, map, half_width, half_height)
-                        .then_some(-1)
-                        .unwrap_or(id);
-
-                    let bottom_right = {
-                        let mut coords = coords;
-                        coords.1.y -= 1;
-                        if coords.1.y == -1 {
-                            coords.1.y = 4;
-                            coords.0.y -= 1;
-                        }
-                        is_obstacle(coords, map, half_width, half_height)
-                            .then_some(-1)
-                            .unwrap_or_else(|| {
-                                coords_to_polygon_id(coords, half_width, half_height)
-                            })
-                    } as isize;
-                    let top_left = {
-                        let mut coords = coords;
-                        coords.1.x -= 1;
-                        if coords.1.x == -1 {
-                            coords.1.x = 4;
-                            coords.0.x += 1;
-                        }
-                        is_obstacle(coords, map, half_width, half_height)
-                            .then_some(-1)
-                            .unwrap_or_else(|| {
-                                coords_to_polygon_id(coords, half_width, half_height)
-                            })
-                    } as isize;
-                    let bottom_left = {
-                        let mut coords = coords;
-                        coords.1.y -= 1;
-                        if coords.1.y == -1 {
-                            coords.1.y = 4;
-                            coords.0.y -= 1;
-                        }
-                        coords.1.x -= 1;
-                        if coords.1.x == -1 {
-                            coords.1.x = 4;
-                            coords.0.x += 1;
-                        }
-                        is_obstacle(coords, map, half_width, half_height)
-                            .then_some(-1)
-                            .unwrap_or_else(|| {
-                                coords_to_polygon_id(coords, half_width, half_height)
-                            })
-                    } as isize;
-
-                    mesh.vertices[id as usize] = polyanya::Vertex::new(
-                        map_to_world(coords) + Vec2::new(0.5, -0.5) / def as f32,
-                        vec![top_left, top_right, bottom_right, bottom_left],
+                    build_polygon(
+                        &mut mesh,
+                        coords,
+                        map,
+                        plane,
+                        half_width,
+                        half_height,
+                        config,
                     );
-                    if top_right != -1 {
-                        mesh.polygons[id as usize] = polyanya::Polygon {
-                            vertices: vec![
-                                id as usize + 1,
-                                id as usize,
-                                id as usize + (half_width as usize * 2 + 1) * def as usize,
-                                id as usize + 1 + (half_width as usize * 2 + 1) * def as usize,
-                            ],
-                            is_one_way: false,
-                        };
-                    }
                 }
             }
         }
@@ -856,8 +1062,88 @@ fn new_mesh_from_map(
     mesh
 }
 
+/// Set the polygon of the lot at `coords`, and the vertex at its corner, from the map.
+fn build_polygon(
+    mesh: &mut polyanya::Mesh,
+    coords: (IVec2, IVec2),
+    map: &Map,
+    plane: Plane,
+    half_width: isize,
+    half_height: isize,
+    config: &TerrainConfig,
+) {
+    let def = config.lots_per_tile as usize;
+    let id = coords_to_polygon_id(coords, half_width, half_height, config) as isize;
+    let top_right = is_obstacle(coords, map, plane, half_width, half_height)
+        .then_some(-1)
+        .unwrap_or(id);
+
+    let bottom_right = {
+        let mut coords = coords;
+        coords.1.y -= 1;
+        if coords.1.y == -1 {
+            coords.1.y = def as i32 - 1;
+            coords.0.y -= 1;
+        }
+        is_obstacle(coords, map, plane, half_width, half_height)
+            .then_some(-1)
+            .unwrap_or_else(|| coords_to_polygon_id(coords, half_width, half_height, config))
+    } as isize;
+    let top_left = {
+        let mut coords = coords;
+        coords.1.x -= 1;
+        if coords.1.x == -1 {
+            coords.1.x = def as i32 - 1;
+            coords.0.x += 1;
+        }
+        is_obstacle(coords, map, plane, half_width, half_height)
+            .then_some(-1)
+            .unwrap_or_else(|| coords_to_polygon_id(coords, half_width, half_height, config))
+    } as isize;
+    let bottom_left = {
+        let mut coords = coords;
+        coords.1.y -= 1;
+        if coords.1.y == -1 {
+            coords.1.y = def as i32 - 1;
+            coords.0.y -= 1;
+        }
+        coords.1.x -= 1;
+        if coords.1.x == -1 {
+            coords.1.x = def as i32 - 1;
+            coords.0.x += 1;
+        }
+        is_obstacle(coords, map, plane, half_width, half_height)
+            .then_some(-1)
+            .unwrap_or_else(|| coords_to_polygon_id(coords, half_width, half_height, config))
+    } as isize;
+
+    mesh.vertices[id as usize] = polyanya::Vertex::new(
+        map_to_world(coords, config) + Vec2::new(0.5, -0.5) / def as f32,
+        vec![top_left, top_right, bottom_right, bottom_left],
+    );
+    mesh.polygons[id as usize] = if top_right != -1 {
+        polyanya::Polygon {
+            vertices: vec![
+                id as usize + 1,
+                id as usize,
+                id as usize + (half_width as usize * 2 + 1) * def,
+                id as usize + 1 + (half_width as usize * 2 + 1) * def,
+            ],
+            is_one_way: false,
+        }
+    } else {
+        polyanya::Polygon::EMPTY
+    };
+}
+
 #[inline(always)]
-fn is_obstacle(coords: (IVec2, IVec2), map: &Map, half_width: isize, half_height: isize) -> bool {
+fn is_obstacle(
+    coords: (IVec2, IVec2),
+    map: &Map,
+    plane: Plane,
+    half_width: isize,
+    half_height: isize,
+) -> bool {
     if !(-half_width..=half_width).contains(&(coords.0.x as isize))
         || !(-half_height..=half_height).contains(&(coords.0.y as isize))
     {
@@ -865,7 +1151,7 @@ fn is_obstacle(coords: (IVec2, IVec2), map: &Map, half_width: isize, half_height
     }
     if map
         .lots
-        .get(&(coords.0, Plane::Material))
+        .get(&(coords.0, plane))
         .and_then(|lot| lot.get(&coords.1))
         .filter(|o| !o.is_path_free())
         .is_some()
@@ -876,16 +1162,39 @@ fn is_obstacle(coords: (IVec2, IVec2), map: &Map, half_width: isize, half_height
 }
 
 #[inline(always)]
-fn coords_to_polygon_id(coords: (IVec2, IVec2), half_width: isize, half_height: isize) -> i32 {
-    let mut world = (map_to_world(coords) + Vec2::new(0.5, 0.5)) * LOW_DEF as f32;
-    world.x = LOW_DEF as f32 - world.x;
+fn coords_to_polygon_id(
+    coords: (IVec2, IVec2),
+    half_width: isize,
+    half_height: isize,
+    config: &TerrainConfig,
+) -> i32 {
+    let low_def = config.lots_per_tile;
+    let mut world = (map_to_world(coords, config) + Vec2::new(0.5, 0.5)) * low_def as f32;
+    world.x = low_def as f32 - world.x;
 
     let world = IVec2::new(world.x.floor() as i32, world.y.floor() as i32)
         + IVec2::new(
-            half_width as i32 * LOW_DEF as i32,
-            half_height as i32 * LOW_DEF as i32,
+            half_width as i32 * low_def as i32,
+            half_height as i32 * low_def as i32,
         );
-    world.x + world.y * ((2 * half_width as i32 + 1) * LOW_DEF as i32)
+    world.x + world.y * ((2 * half_width as i32 + 1) * low_def as i32)
+}
+
+#[inline(always)]
+fn polygon_id_to_coords(
+    id: usize,
+    half_width: isize,
+    half_height: isize,
+    config: &TerrainConfig,
+) -> (IVec2, IVec2) {
+    let low_def = config.lots_per_tile as i32;
+    let row = (2 * half_width as i32 + 1) * low_def;
+    let x = id as i32 % row - half_width as i32 * low_def;
+    let y = id as i32 / row - half_height as i32 * low_def;
+    (
+        IVec2::new(-x.div_euclid(low_def), y.div_euclid(low_def)),
+        IVec2::new(x.rem_euclid(low_def), y.rem_euclid(low_def)),
+    )
 }
 
 #[cfg(test)]
@@ -893,12 +1202,17 @@ mod tests {
     use bevy::prelude::IVec2;
     use bevy::prelude::Vec2;
     use bevy::utils::HashMap;
+    use polyanya::Polygon;
 
+    use crate::game::heightmap::TerrainConfig;
     use crate::game::terra::Plane;
+    use crate::game::terrain_spawner::NavMesh;
     use crate::game::terrain_spawner::Occupying;
-    use crate::game::terrain_spawner::Pathfinding;
+    use crate::game::towers::TowerKind;
 
-    use super::coords_to_polygon_id as id;
+    fn id(coords: (IVec2, IVec2), half_width: isize, half_height: isize) -> i32 {
+        super::coords_to_polygon_id(coords, half_width, half_height, &TerrainConfig::default())
+    }
 
     #[test]
     fn coords_id_size_0() {
@@ -935,7 +1249,7 @@ mod tests {
         let map = super::Map {
             lots: Default::default(),
         };
-        let mesh = new_mesh_from_map(&map, 0, 0, 5);
+        let mesh = new_mesh_from_map(&map, Plane::Material, 0, 0, &TerrainConfig::default());
         // dbg!(&mesh.vertices);
         dbg!(&mesh.polygons);
         dbg!(mesh.vertices.len());
@@ -947,12 +1261,14 @@ mod tests {
         let mut map = super::Map {
             lots: Default::default(),
         };
-        let mut mesh_cut = new_mesh_from_map(&map, 0, 0, 5);
-        Pathfinding::inner_cut_polygon_out(
+        let mut mesh_cut =
+            new_mesh_from_map(&map, Plane::Material, 0, 0, &TerrainConfig::default());
+        NavMesh::inner_cut_polygon_out(
             &mut mesh_cut,
             (IVec2::new(0, 0), IVec2::new(2, 2)),
             0,
             0,
+            &TerrainConfig::default(),
         );
 
         let mut crystal = HashMap::new();
@@ -960,7 +1276,7 @@ mod tests {
         map.lots
             .insert((IVec2::new(0, 0), Plane::Material), crystal);
 
-        let mesh_built = new_mesh_from_map(&map, 0, 0, 5);
+        let mesh_built = new_mesh_from_map(&map, Plane::Material, 0, 0, &TerrainConfig::default());
 
         assert_eq!(mesh_cut.vertices.len(), mesh_built.vertices.len());
         for i in 0..mesh_cut.vertices.len() {
@@ -979,7 +1295,7 @@ mod tests {
         let map = super::Map {
             lots: Default::default(),
         };
-        let mesh = new_mesh_from_map(&map, 0, 0, 5);
+        let mesh = new_mesh_from_map(&map, Plane::Material, 0, 0, &TerrainConfig::default());
 
         let from = Vec2::new(0.2, 0.0);
         let to = Vec2::new(-0.2, 0.0);
@@ -995,7 +1311,7 @@ mod tests {
         let map = super::Map {
             lots: Default::default(),
         };
-        let mesh = new_mesh_from_map(&map, 1, 1, 5);
+        let mesh = new_mesh_from_map(&map, Plane::Material, 1, 1, &TerrainConfig::default());
 
         let from = Vec2::new(0.2, 0.0);
         let to = Vec2::new(-0.2, 0.0);
@@ -1013,4 +1329,106 @@ mod tests {
         let to = Vec2::new(-1.0, 0.0);
         assert_eq!(mesh.path(from, to).len, from.distance(to));
     }
+
+    #[test]
+    fn mesh_grows_with_map() {
+        let config = TerrainConfig::default();
+        let mut map = super::Map {
+            lots: Default::default(),
+        };
+        let far = IVec2::new(8, -1);
+        assert!(!NavMesh::from_map(&map, Plane::Material, &config).covers(far));
+
+        map.lots.insert((far, Plane::Material), HashMap::new());
+        let pathfinding = NavMesh::from_map(&map, Plane::Material, &config);
+        assert!(pathfinding.covers(far));
+
+        let from = super::map_to_world((far, IVec2::new(2, 2)), &config);
+        assert!(pathfinding.mesh.path(from, Vec2::ZERO).complete);
+    }
+
+    #[test]
+    fn polygon_id_round_trip() {
+        let config = TerrainConfig::default();
+        for id in 0..(7 * 5 * 3 * 5) {
+            let coords = super::polygon_id_to_coords(id, 3, 1, &config);
+            assert_eq!(
+                super::coords_to_polygon_id(coords, 3, 1, &config),
+                id as i32
+            );
+        }
+    }
+
+    #[test]
+    fn mesh_refreshing() {
+        let config = TerrainConfig::default();
+        let mut map = super::Map {
+            lots: Default::default(),
+        };
+        map.lots
+            .insert((IVec2::new(0, 0), Plane::Material), HashMap::new());
+        let mut pathfinding = NavMesh::from_map(&map, Plane::Material, &config);
+
+        let mut blocked = HashMap::new();
+        blocked.insert(IVec2::new(4, 2), Occupying::Tower(TowerKind::Basic));
+        blocked.insert(IVec2::new(0, 0), Occupying::Mountain);
+        map.lots
+            .insert((IVec2::new(1, 0), Plane::Material), blocked);
+        pathfinding.refresh_lot((IVec2::new(1, 0), IVec2::new(4, 2)), &map, &config);
+        pathfinding.refresh_lot((IVec2::new(1, 0), IVec2::new(0, 0)), &map, &config);
+        assert_eq!(
+            pathfinding.mesh.vertices,
+            NavMesh::from_map(&map, Plane::Material, &config)
+                .mesh
+                .vertices
+        );
+        assert_eq!(
+            pathfinding.mesh.polygons,
+            NavMesh::from_map(&map, Plane::Material, &config)
+                .mesh
+                .polygons
+        );
+
+        map.lots
+            .get_mut(&(IVec2::new(1, 0), Plane::Material))
+            .unwrap()
+            .remove(&IVec2::new(4, 2));
+        pathfinding.refresh_lot((IVec2::new(1, 0), IVec2::new(4, 2)), &map, &config);
+        assert_eq!(
+            pathfinding.mesh.vertices,
+            NavMesh::from_map(&map, Plane::Material, &config)
+                .mesh
+                .vertices
+        );
+        assert_eq!(
+            pathfinding.mesh.polygons,
+            NavMesh::from_map(&map, Plane::Material, &config)
+                .mesh
+                .polygons
+        );
+    }
+
+    #[test]
+    fn obstacles_per_plane() {
+        let config = TerrainConfig::default();
+        let mut map = super::Map {
+            lots: Default::default(),
+        };
+        let mut blocked = HashMap::new();
+        blocked.insert(IVec2::new(1, 1), Occupying::Mountain);
+        map.lots
+            .insert((IVec2::new(0, 0), Plane::Material), HashMap::new());
+        map.lots
+            .insert((IVec2::new(0, 0), Plane::Ethereal), blocked);
+        let pathfinding = super::Pathfinding::from_map(&map, &config);
+        let id = super::coords_to_polygon_id((IVec2::new(0, 0), IVec2::new(1, 1)), 5, 5, &config);
+        assert_ne!(
+            pathfinding.plane(Plane::Material).mesh.polygons[id as usize],
+            Polygon::EMPTY
+        );
+        assert_eq!(
+            pathfinding.plane(Plane::Ethereal).mesh.polygons[id as usize],
+            Polygon::EMPTY
+        );
+    }
 }
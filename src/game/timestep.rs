@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*, transform::TransformSystem};
+
+use crate::GameState;
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationTime>()
+            .init_resource::<StepClock>()
+            .init_resource::<Interpolating>()
+            .add_stage_after(
+                CoreStage::Update,
+                SimulationStage,
+                SystemStage::parallel().with_run_criteria(fixed_step),
+            )
+            .add_system_to_stage(
+                SimulationStage,
+                restore_transforms.exclusive_system().at_start(),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate_transforms.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Seconds between two steps of the simulation.
+pub(crate) const STEP: f64 = 1.0 / 60.0;
+
+/// Most steps run in a single frame. When frames take longer than that, the simulation slows
+/// down instead of taking ever longer frames to catch up.
+const MAX_STEPS_PER_FRAME: u32 = 5;
+
+/// Stage running the game logic by steps of [`STEP`], as many times as needed to catch up with
+/// the frame time (up to [`MAX_STEPS_PER_FRAME`]), so that a run plays the same whatever the
+/// frame rate.
+#[derive(StageLabel)]
+pub(crate) struct SimulationStage;
+
+/// Time as seen by systems of the [`SimulationStage`], advancing by [`STEP`] each time they run.
+#[derive(Default)]
+pub(crate) struct SimulationTime;
+
+impl SimulationTime {
+    pub(crate) fn delta(&self) -> Duration {
+        Duration::from_secs_f64(STEP)
+    }
+
+    pub(crate) fn delta_seconds(&self) -> f32 {
+        STEP as f32
+    }
+}
+
+/// Time not yet simulated, and steps already run during the current frame.
+#[derive(Default)]
+struct StepClock {
+    accumulator: f64,
+    steps: u32,
+    looping: bool,
+}
+
+/// Run criteria of the [`SimulationStage`], running it once for each [`STEP`] elapsed since the
+/// last frame.
+fn fixed_step(time: Res<Time>, mut clock: ResMut<StepClock>) -> ShouldRun {
+    if !clock.looping {
+        clock.accumulator += time.delta_seconds_f64();
+        clock.steps = 0;
+    }
+    if clock.accumulator >= STEP && clock.steps < MAX_STEPS_PER_FRAME {
+        clock.accumulator -= STEP;
+        clock.steps += 1;
+        clock.looping = true;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        // drop the steps that couldn't be caught up with, keeping what's needed to interpolate
+        clock.accumulator %= STEP;
+        clock.looping = false;
+        ShouldRun::No
+    }
+}
+
+/// Run criteria for systems of the [`SimulationStage`] that only run during a game.
+pub(crate) fn playing(state: Res<State<GameState>>) -> ShouldRun {
+    if *state.current() == GameState::Playing {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Entity moved by the simulation, drawn between its last two positions so that it moves
+/// smoothly when the frame rate doesn't match the simulation rate.
+#[derive(Component)]
+pub(crate) struct Interpolated {
+    previous: Transform,
+    current: Transform,
+}
+
+impl Interpolated {
+    pub(crate) fn new(transform: Transform) -> Self {
+        Self {
+            previous: transform,
+            current: transform,
+        }
+    }
+}
+
+/// Whether transforms hold interpolated positions instead of the ones from the simulation.
+#[derive(Default)]
+struct Interpolating(bool);
+
+/// Give back their simulated position to interpolated entities before a step.
+fn restore_transforms(
+    mut interpolating: ResMut<Interpolating>,
+    mut entities: Query<(&mut Transform, &mut Interpolated)>,
+) {
+    for (mut transform, mut interpolated) in &mut entities {
+        if interpolating.0 {
+            *transform = interpolated.current;
+        }
+        interpolated.previous = *transform;
+    }
+    interpolating.0 = false;
+}
+
+fn interpolate_transforms(
+    mut interpolating: ResMut<Interpolating>,
+    mut entities: Query<(&mut Transform, &mut Interpolated)>,
+    clock: Res<StepClock>,
+) {
+    let overstep = ((clock.accumulator / STEP) as f32).min(1.0);
+    for (mut transform, mut interpolated) in &mut entities {
+        if !interpolating.0 {
+            interpolated.current = *transform;
+        }
+        let previous = interpolated.previous;
+        let current = interpolated.current;
+        *transform = Transform {
+            translation: previous.translation.lerp(current.translation, overstep),
+            rotation: previous.rotation.slerp(current.rotation, overstep),
+            scale: previous.scale.lerp(current.scale, overstep),
+        };
+    }
+    interpolating.0 = true;
+}
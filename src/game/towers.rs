@@ -1,26 +1,662 @@
-use bevy::prelude::*;
+use std::{
+    f32::consts::{PI, TAU},
+    time::Duration,
+};
 
-use crate::{assets::SceneryAssets, GameState};
+use bevy::{
+    ecs::world::Mut,
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology, view::NoFrustumCulling},
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
 
-use super::{stats::GameTag, terra::Plane, zombies::Zombie, PlayingState};
+use crate::{
+    assets::{BuildingAssets, SceneryAssets},
+    GameState,
+};
+
+use super::{
+    balance::{Balance, ImpactStats},
+    effects::{Effect, StatusEffects},
+    flow_field::{FlowFields, PathfindingMode},
+    heightmap::TerrainConfig,
+    on_playing_update,
+    spatial::{index_zombies, lots_near, ZombieGrid},
+    stats::GameTag,
+    terra::Plane,
+    terrain_spawner::{
+        world_to_map, CursorPosition, MeshCache, NavMesh, NavmeshChanged, Pathfinding,
+    },
+    timestep::{playing, Interpolated, SimulationStage, SimulationTime},
+    ui::{in_tower_panel, in_ui_zone},
+    zombies::{death, is_dead, IdleZombie, Pathless, Zombie, ZombieKind},
+    PlayingState,
+};
 
 pub(crate) struct Plugin;
 
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(trigger_attack)
-                .with_system(move_missiles),
-        );
+        app.init_resource::<InspectedTower>()
+            .init_resource::<RangeRing>()
+            .add_event::<TowerDestroyed>()
+            .add_event::<ZombieHit>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
+            .add_system_set(
+                SystemSet::on_enter(PlayingState::Building).with_system(stop_inspecting),
+            )
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(playing)
+                    .with_system(trigger_attack.after(index_zombies))
+                    .with_system(move_missiles.after(index_zombies))
+                    // routes are inserted before dead breakers are despawned
+                    .with_system(steer_breakers.before(death))
+                    .with_system(attack_towers.after(steer_breakers)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(display_tower_range)
+                    .with_system(refresh_tower_stats),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(on_playing_update(PlayingState::Playing))
+                    .with_system(inspect_tower),
+            );
+    }
+}
+
+/// Kinds of towers that can be built from the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum TowerKind {
+    Basic,
+    Splash,
+    Slow,
+    Chain,
+    Sniper,
+    DualPlane,
+}
+
+impl TowerKind {
+    pub(crate) const ALL: [TowerKind; 6] = [
+        TowerKind::Basic,
+        TowerKind::Splash,
+        TowerKind::Slow,
+        TowerKind::Chain,
+        TowerKind::Sniper,
+        TowerKind::DualPlane,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            TowerKind::Basic => "Basic",
+            TowerKind::Splash => "Mortar",
+            TowerKind::Slow => "Frost",
+            TowerKind::Chain => "Tesla",
+            TowerKind::Sniper => "Sniper",
+            TowerKind::DualPlane => "Rift",
+        }
+    }
+
+    /// Can a tower on `tower_plane` shoot at a zombie on `zombie_plane`.
+    pub(crate) fn reaches(self, tower_plane: Plane, zombie_plane: Plane) -> bool {
+        self == TowerKind::DualPlane || tower_plane == zombie_plane
+    }
+
+    pub(crate) fn scene(self, plane: Plane, building_assets: &BuildingAssets) -> Handle<Scene> {
+        // the dual plane tower uses the model from the other plane
+        let plane = if self == TowerKind::DualPlane {
+            plane.next()
+        } else {
+            plane
+        };
+        match plane {
+            Plane::Material => building_assets.material_tower.clone_weak(),
+            Plane::Ethereal => building_assets.ethereal_tower.clone_weak(),
+        }
+    }
+
+    /// Scale of the model, to tell the kinds apart.
+    pub(crate) fn scale(self) -> Vec3 {
+        let height = match self {
+            TowerKind::Basic | TowerKind::DualPlane => 1.0,
+            TowerKind::Splash => 0.7,
+            TowerKind::Slow => 0.85,
+            TowerKind::Chain => 1.2,
+            TowerKind::Sniper => 1.6,
+        };
+        Vec3::new(1.0, height, 1.0)
+    }
+}
+
+/// Which zombie in range a tower shoots at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Targeting {
+    /// Closest to the crystal along its path.
+    First,
+    /// Furthest from the crystal along its path.
+    Last,
+    Strongest,
+    Weakest,
+    Closest,
+    Fastest,
+}
+
+impl Targeting {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Targeting::First => "first",
+            Targeting::Last => "last",
+            Targeting::Strongest => "strongest",
+            Targeting::Weakest => "weakest",
+            Targeting::Closest => "closest",
+            Targeting::Fastest => "fastest",
+        }
+    }
+
+    pub(crate) fn next(self) -> Self {
+        match self {
+            Targeting::First => Targeting::Last,
+            Targeting::Last => Targeting::Strongest,
+            Targeting::Strongest => Targeting::Weakest,
+            Targeting::Weakest => Targeting::Closest,
+            Targeting::Closest => Targeting::Fastest,
+            Targeting::Fastest => Targeting::First,
+        }
     }
 }
 
+/// Highest level a tower can be upgraded to.
+pub(crate) const MAX_LEVEL: u32 = 3;
+
 #[derive(Component)]
 pub(crate) struct Tower {
+    pub(crate) kind: TowerKind,
     pub(crate) timer: Timer,
     pub(crate) strength: f32,
+    pub(crate) range: f32,
     pub(crate) plane: Plane,
+    pub(crate) targeting: Targeting,
+    pub(crate) health: f32,
+    pub(crate) max_health: f32,
+    pub(crate) level: u32,
+    pub(crate) kills: u32,
+    pub(crate) damage: f32,
+    /// Credits spent on building and upgrading it.
+    pub(crate) invested: u32,
+}
+
+impl Tower {
+    pub(crate) fn new(kind: TowerKind, plane: Plane, balance: &Balance) -> Self {
+        let stats = balance.tower(kind);
+        Tower {
+            kind,
+            timer: Timer::from_seconds(stats.reload, true),
+            strength: stats.strength,
+            range: stats.range,
+            plane,
+            targeting: Targeting::First,
+            health: stats.health,
+            max_health: stats.health,
+            level: 1,
+            kills: 0,
+            damage: 0.0,
+            invested: stats.cost,
+        }
+    }
+
+    /// Cost of the next level, `None` when already at the highest level.
+    pub(crate) fn upgrade_cost(&self, balance: &Balance) -> Option<u32> {
+        (self.level < MAX_LEVEL).then(|| balance.tower(self.kind).cost * self.level)
+    }
+
+    pub(crate) fn upgrade(&mut self, balance: &Balance) {
+        if let Some(cost) = self.upgrade_cost(balance) {
+            self.level += 1;
+            self.invested += cost;
+            self.apply_balance(balance);
+            self.health = self.max_health;
+        }
+    }
+
+    /// Compute its stats from the ones of its kind and its level, keeping the same part of its
+    /// health.
+    pub(crate) fn apply_balance(&mut self, balance: &Balance) {
+        let stats = balance.tower(self.kind);
+        let upgrade = &balance.upgrade;
+        let levels = self.level as i32 - 1;
+        let health_left = if self.max_health > 0.0 {
+            self.health / self.max_health
+        } else {
+            1.0
+        };
+        self.strength = stats.strength * upgrade.strength.powi(levels);
+        self.range = stats.range * upgrade.range.powi(levels);
+        self.max_health = stats.health * upgrade.health.powi(levels);
+        self.health = self.max_health * health_left;
+        self.timer.set_duration(Duration::from_secs_f32(
+            stats.reload * upgrade.reload.powi(levels),
+        ));
+    }
+
+    /// Credits given back when selling it.
+    pub(crate) fn refund(&self, balance: &Balance) -> u32 {
+        (self.invested as f32 * balance.refund) as u32
+    }
+}
+
+/// Give towers already built the stats of the balance when it's reloaded.
+fn refresh_tower_stats(balance: Res<Balance>, mut towers: Query<&mut Tower>) {
+    if !balance.is_changed() {
+        return;
+    }
+    for mut tower in &mut towers {
+        tower.apply_balance(&balance);
+    }
+}
+
+/// Sent when zombies destroyed a tower.
+pub(crate) struct TowerDestroyed(pub(crate) Entity);
+
+/// Distance from which zombies can hit a tower.
+const TOWER_ATTACK_RANGE: f32 = 0.3;
+
+/// Breakers stop walking to a tower when this close to it.
+const BREAKER_APPROACH: f32 = TOWER_ATTACK_RANGE * 0.8;
+
+/// Zombies able to damage towers hit the closest one next to them, either while walking by or
+/// when they have no path to the crystal.
+fn attack_towers(
+    attackers: Query<(&Transform, &ZombieKind), Or<(With<Zombie>, With<IdleZombie>)>>,
+    mut towers: Query<(Entity, &Transform, &mut Tower)>,
+    config: Res<TerrainConfig>,
+    time: Res<SimulationTime>,
+    playing_state: Res<State<PlayingState>>,
+    mut destroyed: EventWriter<TowerDestroyed>,
+) {
+    if *playing_state.current() == PlayingState::SwitchingPlane {
+        return;
+    }
+    let by_lot = towers
+        .iter()
+        .map(|(entity, transform, _)| {
+            let position = Vec2::new(transform.translation.x, transform.translation.z);
+            (world_to_map(position, &config), entity)
+        })
+        .collect::<HashMap<_, _>>();
+    for (transform, kind) in &attackers {
+        if kind.tower_damage <= 0.0 {
+            continue;
+        }
+        let position = Vec2::new(transform.translation.x, transform.translation.z);
+        let closest = lots_near(position, TOWER_ATTACK_RANGE, &config)
+            .filter_map(|lot| by_lot.get(&lot))
+            .filter_map(|entity| {
+                let (_, tower_transform, _) = towers.get(*entity).ok()?;
+                let distance = position.distance_squared(Vec2::new(
+                    tower_transform.translation.x,
+                    tower_transform.translation.z,
+                ));
+                (distance < TOWER_ATTACK_RANGE * TOWER_ATTACK_RANGE).then(|| (*entity, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((entity, _)) = closest {
+            let (_, _, mut tower) = towers.get_mut(entity).unwrap();
+            let was_standing = tower.health > 0.0;
+            tower.health -= kind.tower_damage * time.delta_seconds();
+            if was_standing && tower.health <= 0.0 {
+                destroyed.send(TowerDestroyed(entity));
+            }
+        }
+    }
+}
+
+/// Way from a breaker to a lot next to the tower it goes to break.
+#[derive(Component)]
+struct BreakerRoute {
+    tower: Entity,
+    path: Vec<Vec2>,
+    current: usize,
+}
+
+/// Navmesh path from `from` to a lot next to the tower at `tower`, `None` if none can be
+/// reached.
+fn route_to_tower(
+    navmesh: &NavMesh,
+    from: Vec2,
+    tower: Vec2,
+    config: &TerrainConfig,
+) -> Option<Vec<Vec2>> {
+    // just past the edge of the tower's lot, to be close enough to attack it
+    let distance = 0.6 / config.lots_per_tile as f32;
+    let mut goals = [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y].map(|side| tower + side * distance);
+    goals.sort_by(|a, b| {
+        a.distance_squared(from)
+            .total_cmp(&b.distance_squared(from))
+    });
+    goals.into_iter().find_map(|goal| {
+        let path = navmesh.mesh.path(from, goal);
+        path.complete.then(|| path.path)
+    })
+}
+
+/// Zombies able to damage towers that have no path to the crystal walk to the closest tower
+/// along the navmesh, to break their way through.
+fn steer_breakers(
+    mut commands: Commands,
+    mut breakers: Query<
+        (
+            Entity,
+            &mut Transform,
+            &ZombieKind,
+            &IdleZombie,
+            Option<&StatusEffects>,
+            Option<&mut BreakerRoute>,
+        ),
+        With<Pathless>,
+    >,
+    towers: Query<(Entity, &Transform), (With<Tower>, Without<IdleZombie>)>,
+    time: Res<SimulationTime>,
+    playing_state: Res<State<PlayingState>>,
+    pathfinding: Res<Pathfinding>,
+    mut navmesh_changes: EventReader<NavmeshChanged>,
+    (mesh_cache, meshes, config): (Res<MeshCache>, Res<Assets<Mesh>>, Res<TerrainConfig>),
+) {
+    let changed = navmesh_changes
+        .iter()
+        .map(|change| match change {
+            NavmeshChanged::Lots(plane, _) | NavmeshChanged::Rebuilt(plane) => *plane,
+        })
+        .collect::<Vec<_>>();
+    if *playing_state.current() == PlayingState::SwitchingPlane {
+        return;
+    }
+    for (entity, mut transform, kind, idle, effects, route) in &mut breakers {
+        if kind.tower_damage <= 0.0 {
+            continue;
+        }
+        let position = Vec2::new(transform.translation.x, transform.translation.z);
+        let mut route = match route {
+            Some(route) if towers.get(route.tower).is_ok() && !changed.contains(&idle.plane) => {
+                route
+            }
+            _ => {
+                let closest = towers
+                    .iter()
+                    .map(|(tower, transform)| {
+                        (
+                            tower,
+                            Vec2::new(transform.translation.x, transform.translation.z),
+                        )
+                    })
+                    .min_by(|(_, a), (_, b)| {
+                        a.distance_squared(position)
+                            .total_cmp(&b.distance_squared(position))
+                    });
+                if let Some((tower, target)) = closest {
+                    // without a way to the tower, the breaker waits for the navmesh to change
+                    let path =
+                        route_to_tower(pathfinding.plane(idle.plane), position, target, &config)
+                            .unwrap_or_default();
+                    commands.entity(entity).insert(BreakerRoute {
+                        tower,
+                        path,
+                        current: 0,
+                    });
+                }
+                continue;
+            }
+        };
+        let (_, tower) = towers.get(route.tower).unwrap();
+        let tower = Vec2::new(tower.translation.x, tower.translation.z);
+        if tower.distance_squared(position) <= BREAKER_APPROACH.powi(2) {
+            continue;
+        }
+        let target = match route.path.get(route.current) {
+            Some(target) => *target,
+            None => continue,
+        };
+        let factor = effects.map(|effects| effects.speed_factor()).unwrap_or(1.0);
+        let step = time.delta_seconds() * (0.2 + idle.speed) * factor;
+        let next = if target.distance(position) <= step {
+            route.current += 1;
+            target
+        } else {
+            position + (target - position).normalize() * step
+        };
+        let height = mesh_cache
+            .height_at(next, idle.plane, &meshes, &config)
+            .unwrap_or(transform.translation.y);
+        transform.translation = Vec3::new(next.x, height, next.y);
+        if target != position {
+            let direction = target - position;
+            transform.look_at(
+                Vec3::new(next.x + direction.x, height, next.y + direction.y),
+                Vec3::Y,
+            );
+            transform.rotate(Quat::from_rotation_y(PI));
+        }
+    }
+}
+
+/// Marks the models of a tower and of the block it leaves on the other plane.
+#[derive(Component)]
+pub(crate) struct TowerModel {
+    pub(crate) lot: IVec2,
+}
+
+/// Tower shown in the info panel.
+#[derive(Default)]
+pub(crate) struct InspectedTower(pub(crate) Option<Entity>);
+
+/// Material of the rings showing how far a tower reaches.
+pub(crate) struct RangeRing {
+    pub(crate) material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for RangeRing {
+    fn from_world(world: &mut World) -> Self {
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::rgba(1.0, 1.0, 1.0, 0.6),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                cull_mode: None,
+                ..default()
+            });
+        RangeRing { material }
+    }
+}
+
+/// Number of segments of a range ring.
+const RING_SEGMENTS: u32 = 64;
+/// Width of the band drawn by a range ring, the same whatever the range.
+const RING_WIDTH: f32 = 0.02;
+/// Distance between a range ring and the ground.
+const RING_ELEVATION: f32 = 0.01;
+
+impl RangeRing {
+    /// Band around `center` at `range`, following the ground given by `height`. Vertices are in
+    /// world space, the ring is meant to be drawn without transform.
+    pub(crate) fn mesh(center: Vec2, range: f32, height: impl Fn(Vec2) -> Option<f32>) -> Mesh {
+        let mut positions = Vec::with_capacity((RING_SEGMENTS as usize + 1) * 2);
+        for i in 0..=RING_SEGMENTS {
+            let angle = i as f32 / RING_SEGMENTS as f32 * TAU;
+            let direction = Vec2::new(angle.cos(), angle.sin());
+            for radius in [range - RING_WIDTH / 2.0, range + RING_WIDTH / 2.0] {
+                let point = center + direction * radius;
+                let y = height(point).unwrap_or_default() + RING_ELEVATION;
+                positions.push([point.x, y, point.y]);
+            }
+        }
+        let indices = (0..RING_SEGMENTS * 2)
+            .step_by(2)
+            .flat_map(|i| [i, i + 2, i + 1, i + 2, i + 3, i + 1])
+            .collect();
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            vec![Vec3::Y.to_array(); positions.len()],
+        );
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            vec![Vec2::ZERO.to_array(); positions.len()],
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+/// Marks the ring around the hovered or inspected tower.
+#[derive(Component)]
+struct TowerRangeRing;
+
+fn setup(mut commands: Commands, ring: Res<RangeRing>, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(InspectedTower::default());
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(RangeRing::mesh(Vec2::ZERO, 1.0, |_| None)),
+            material: ring.material.clone_weak(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert_bundle((TowerRangeRing, NotShadowCaster, NoFrustumCulling, GameTag));
+}
+
+fn tower_at<'a>(
+    mut towers: impl Iterator<Item = (Entity, &'a Transform)>,
+    coords: (IVec2, IVec2),
+    config: &TerrainConfig,
+) -> Option<Entity> {
+    towers
+        .find(|(_, transform)| {
+            world_to_map(
+                Vec2::new(transform.translation.x, transform.translation.z),
+                config,
+            ) == coords
+        })
+        .map(|(entity, _)| entity)
+}
+
+/// Show the range of the tower under the cursor, or of the inspected one.
+fn display_tower_range(
+    towers: Query<(Entity, &Transform, &Tower)>,
+    mut ring: Query<(&Handle<Mesh>, &mut Visibility), With<TowerRangeRing>>,
+    cursor_position: Res<CursorPosition>,
+    inspected: Res<InspectedTower>,
+    config: Res<TerrainConfig>,
+    plane: Res<Plane>,
+    mesh_cache: Res<MeshCache>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut shown: Local<Option<(Entity, f32)>>,
+) {
+    let (handle, mut visibility) = match ring.get_single_mut() {
+        Ok(ring) => ring,
+        Err(_) => return,
+    };
+    let hovered = tower_at(
+        towers
+            .iter()
+            .map(|(entity, transform, _)| (entity, transform)),
+        (cursor_position.map, cursor_position.lot),
+        &config,
+    );
+    match hovered
+        .or(inspected.0)
+        .and_then(|entity| towers.get(entity).ok())
+    {
+        Some((entity, transform, tower)) => {
+            if *shown != Some((entity, tower.range)) || plane.is_changed() {
+                let center = Vec2::new(transform.translation.x, transform.translation.z);
+                let mesh = RangeRing::mesh(center, tower.range, |point| {
+                    mesh_cache.height_at(point, *plane, &meshes, &config)
+                });
+                if let Some(ring) = meshes.get_mut(handle) {
+                    *ring = mesh;
+                }
+                *shown = Some((entity, tower.range));
+            }
+            visibility.is_visible = true;
+        }
+        None => {
+            visibility.is_visible = false;
+            *shown = None;
+        }
+    }
+}
+
+fn stop_inspecting(mut inspected: ResMut<InspectedTower>) {
+    inspected.0 = None;
+}
+
+fn inspect_tower(
+    mouse_button_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    cursor_position: Res<CursorPosition>,
+    towers: Query<(Entity, &Transform), With<Tower>>,
+    mut inspected: ResMut<InspectedTower>,
+    config: Res<TerrainConfig>,
+) {
+    if !mouse_button_input.just_released(MouseButton::Left) {
+        return;
+    }
+    let window = windows.primary();
+    match window.cursor_position() {
+        Some(pos) if !in_ui_zone(pos, window) => {
+            if inspected.0.is_some() && in_tower_panel(pos, window) {
+                return;
+            }
+        }
+        _ => return,
+    }
+    inspected.0 = tower_at(
+        towers.iter(),
+        (cursor_position.map, cursor_position.lot),
+        &config,
+    );
+}
+
+/// What happens when a missile reaches its target.
+#[derive(Clone, Debug)]
+pub(crate) enum Impact {
+    Single,
+    /// Damage all zombies around the target.
+    Splash {
+        radius: f32,
+    },
+    /// Jump to the next closest zombie within `range`, `jumps` more times, keeping `falloff` of
+    /// the strength at each jump.
+    Chain {
+        jumps: u32,
+        range: f32,
+        falloff: f32,
+        hit: Vec<Entity>,
+    },
+}
+
+impl Impact {
+    fn new(stats: &ImpactStats) -> Self {
+        match *stats {
+            ImpactStats::Single => Impact::Single,
+            ImpactStats::Splash { radius } => Impact::Splash { radius },
+            ImpactStats::Chain {
+                jumps,
+                range,
+                falloff,
+            } => Impact::Chain {
+                jumps,
+                range,
+                falloff,
+                hit: vec![],
+            },
+        }
+    }
 }
 
 #[derive(Component)]
@@ -28,50 +664,92 @@ pub(crate) struct Missile {
     pub(crate) strength: f32,
     pub(crate) plane: Plane,
     pub(crate) target: Entity,
+    /// Tower that shot it.
+    pub(crate) source: Entity,
+    pub(crate) speed: f32,
+    pub(crate) impact: Impact,
+    pub(crate) effect: Option<(Effect, f32)>,
 }
 
 fn trigger_attack(
     mut commands: Commands,
-    zombies: Query<(Entity, &Transform, &Zombie)>,
-    mut towers: Query<(&mut Tower, &Transform)>,
-    time: Res<Time>,
+    zombies: Query<(Entity, &Transform, &Zombie, Option<&StatusEffects>)>,
+    mut towers: Query<(Entity, &mut Tower, &Transform)>,
+    time: Res<SimulationTime>,
     playing_state: Res<State<PlayingState>>,
     plane: Res<Plane>,
     scenery: Res<SceneryAssets>,
+    mode: Res<PathfindingMode>,
+    flow_fields: Res<FlowFields>,
+    config: Res<TerrainConfig>,
+    grid: Res<ZombieGrid>,
+    balance: Res<Balance>,
 ) {
     if *playing_state.current() != PlayingState::SwitchingPlane {
-        for (mut tower, tt) in &mut towers {
+        for (source, mut tower, tt) in &mut towers {
             if tower.timer.tick(time.delta()).just_finished() {
-                let mut to_attack = None;
-                for (ze, zt, zombie) in &zombies {
-                    if zombie.plane == tower.plane
-                        && zt.translation.distance_squared(tt.translation) < 4.0
-                    {
-                        to_attack = Some(ze);
-                        break;
+                let range = tower.range;
+                let stats = balance.tower(tower.kind);
+                // the zombie in range with the highest score is attacked
+                let score = |zt: &Transform, zombie: &Zombie| {
+                    let remaining = || {
+                        zombie.remaining_distance(
+                            Vec2::new(zt.translation.x, zt.translation.z),
+                            *mode,
+                            &flow_fields,
+                            &config,
+                        )
+                    };
+                    match tower.targeting {
+                        Targeting::First => -remaining(),
+                        Targeting::Last => remaining(),
+                        Targeting::Strongest => zombie.life,
+                        Targeting::Weakest => -zombie.life,
+                        Targeting::Closest => -zt.translation.distance_squared(tt.translation),
+                        Targeting::Fastest => zombie.speed,
                     }
-                }
-                if let Some(entity_to_attack) = to_attack {
+                };
+                let to_attack = grid
+                    .near(
+                        Vec2::new(tt.translation.x, tt.translation.z),
+                        range,
+                        &config,
+                    )
+                    .filter_map(|entity| zombies.get(entity).ok())
+                    .filter(|(_, zt, zombie, effects)| {
+                        (tower.kind.reaches(tower.plane, zombie.plane)
+                            || effects.map(|e| e.plane_locked()).unwrap_or(false))
+                            && zt.translation.distance_squared(tt.translation) < range * range
+                    })
+                    .map(|(ze, zt, zombie, _)| (ze, zombie.plane, score(zt, zombie)))
+                    .max_by(|a, b| a.2.total_cmp(&b.2));
+                if let Some((entity_to_attack, target_plane, _)) = to_attack {
+                    let transform = Transform::from_translation(Vec3::new(
+                        tt.translation.x,
+                        0.5,
+                        tt.translation.z,
+                    ));
                     commands
                         .spawn_bundle(PbrBundle {
                             mesh: scenery.missile_mesh.clone_weak(),
                             material: scenery.missile_material.clone_weak(),
-                            transform: Transform::from_translation(Vec3::new(
-                                tt.translation.x,
-                                0.5,
-                                tt.translation.z,
-                            )),
+                            transform,
                             visibility: Visibility {
-                                is_visible: *plane == tower.plane,
+                                is_visible: *plane == target_plane,
                             },
                             ..default()
                         })
                         .insert_bundle((
                             Missile {
                                 strength: tower.strength,
-                                plane: tower.plane,
+                                plane: target_plane,
                                 target: entity_to_attack,
+                                source,
+                                speed: stats.missile_speed,
+                                impact: Impact::new(&stats.impact),
+                                effect: stats.effect,
                             },
+                            Interpolated::new(transform),
                             GameTag,
                         ));
                 }
@@ -80,26 +758,158 @@ fn trigger_attack(
     }
 }
 
+/// Damage a zombie, keeping track of the damage done and the kills of the tower responsible.
+pub(crate) fn hurt(life: &mut f32, damage: f32, tower: Option<Mut<Tower>>) {
+    let was_alive = !is_dead(*life);
+    *life -= damage;
+    if let Some(mut tower) = tower {
+        tower.damage += damage;
+        if was_alive && is_dead(*life) {
+            tower.kills += 1;
+        }
+    }
+}
+
+/// Sent when a missile hits a zombie.
+pub(crate) struct ZombieHit {
+    pub(crate) position: Vec3,
+    pub(crate) plane: Plane,
+    pub(crate) damage: f32,
+}
+
+/// Hit a zombie with a missile, applying its effect. Returns the damage done.
+fn strike(
+    zombie: &mut Zombie,
+    effects: Option<Mut<StatusEffects>>,
+    missile: &Missile,
+    tower: Option<Mut<Tower>>,
+) -> f32 {
+    let mut damage = missile.strength;
+    if let Some(mut effects) = effects {
+        damage *= effects.damage_factor();
+        if let Some((effect, duration)) = missile.effect {
+            effects.apply(effect, duration, Some(missile.source));
+        }
+    }
+    hurt(&mut zombie.life, damage, tower);
+    damage
+}
+
 fn move_missiles(
     mut commands: Commands,
-    mut missiles: Query<(Entity, &mut Transform, &Missile)>,
-    mut zombies: Query<(&Transform, &mut Zombie), Without<Missile>>,
-    time: Res<Time>,
+    mut missiles: Query<(Entity, &mut Transform, &mut Missile)>,
+    mut zombies: Query<
+        (Entity, &Transform, &mut Zombie, Option<&mut StatusEffects>),
+        Without<Missile>,
+    >,
+    mut towers: Query<&mut Tower>,
+    time: Res<SimulationTime>,
     playing_state: Res<State<PlayingState>>,
+    grid: Res<ZombieGrid>,
+    config: Res<TerrainConfig>,
+    mut hits: EventWriter<ZombieHit>,
 ) {
     if *playing_state.current() != PlayingState::SwitchingPlane {
-        for (entity, mut transform, missile) in &mut missiles {
-            if let Ok((target, mut zombie)) = zombies.get_mut(missile.target) {
-                let tr = transform.translation;
-                transform.translation +=
-                    (target.translation - tr).normalize() * time.delta_seconds() * 2.0;
+        for (entity, mut transform, mut missile) in &mut missiles {
+            let target = match zombies.get(missile.target) {
+                Ok((_, target, _, _)) => target.translation,
+                Err(_) => {
+                    commands.entity(entity).despawn();
+                    continue;
+                }
+            };
+            // stop on the target instead of going past it
+            let tr = transform.translation;
+            transform.translation +=
+                (target - tr).clamp_length_max(time.delta_seconds() * missile.speed);
+            if transform.translation.distance_squared(target) >= 0.005 {
+                continue;
+            }
 
-                if transform.translation.distance_squared(target.translation) < 0.005 {
+            let hit = missile.target;
+            let plane = missile.plane;
+            match missile.impact.clone() {
+                Impact::Single => {
+                    let (_, zt, mut zombie, effects) = zombies.get_mut(hit).unwrap();
+                    let damage = strike(
+                        &mut zombie,
+                        effects,
+                        &missile,
+                        towers.get_mut(missile.source).ok(),
+                    );
+                    hits.send(ZombieHit {
+                        position: zt.translation,
+                        plane,
+                        damage,
+                    });
+                    commands.entity(entity).despawn();
+                }
+                Impact::Splash { radius } => {
+                    for near in grid.near(Vec2::new(target.x, target.z), radius, &config) {
+                        if let Ok((_, transform, mut zombie, effects)) = zombies.get_mut(near) {
+                            if zombie.plane == plane
+                                && transform.translation.distance_squared(target) < radius * radius
+                            {
+                                let damage = strike(
+                                    &mut zombie,
+                                    effects,
+                                    &missile,
+                                    towers.get_mut(missile.source).ok(),
+                                );
+                                hits.send(ZombieHit {
+                                    position: transform.translation,
+                                    plane,
+                                    damage,
+                                });
+                            }
+                        }
+                    }
                     commands.entity(entity).despawn();
-                    zombie.life -= missile.strength;
                 }
-            } else {
-                commands.entity(entity).despawn();
+                Impact::Chain {
+                    jumps,
+                    range,
+                    falloff,
+                    hit: mut already_hit,
+                } => {
+                    let (_, zt, mut zombie, effects) = zombies.get_mut(hit).unwrap();
+                    let damage = strike(
+                        &mut zombie,
+                        effects,
+                        &missile,
+                        towers.get_mut(missile.source).ok(),
+                    );
+                    hits.send(ZombieHit {
+                        position: zt.translation,
+                        plane,
+                        damage,
+                    });
+                    already_hit.push(hit);
+                    let next = grid
+                        .near(Vec2::new(target.x, target.z), range, &config)
+                        .filter_map(|other| zombies.get(other).ok())
+                        .filter(|(other, _, zombie, _)| {
+                            zombie.plane == plane && !already_hit.contains(other)
+                        })
+                        .map(|(other, transform, _, _)| {
+                            (other, transform.translation.distance_squared(target))
+                        })
+                        .filter(|(_, distance)| *distance < range * range)
+                        .min_by(|a, b| a.1.total_cmp(&b.1));
+                    match next {
+                        Some((next, _)) if jumps > 0 => {
+                            missile.target = next;
+                            missile.strength *= falloff;
+                            missile.impact = Impact::Chain {
+                                jumps: jumps - 1,
+                                range,
+                                falloff,
+                                hit: already_hit,
+                            };
+                        }
+                        _ => commands.entity(entity).despawn(),
+                    }
+                }
             }
         }
     }
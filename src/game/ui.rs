@@ -8,7 +8,14 @@ use crate::{
     GameState,
 };
 
-use super::{stats::Stats, PlayingState};
+use super::{
+    balance::Balance,
+    builder::{SelectedTower, SellTower},
+    stats::Stats,
+    towers::{InspectedTower, Tower, TowerKind},
+    waves::{WaveDirector, WaveEvent},
+    PlayingState,
+};
 
 pub(crate) struct Plugin;
 
@@ -19,7 +26,12 @@ impl bevy::app::Plugin for Plugin {
             .add_system_set(
                 SystemSet::on_update(GameState::Playing)
                     .with_system(button_system)
-                    .with_system(update_ui),
+                    .with_system(update_ui)
+                    .with_system(display_waves)
+                    .with_system(display_palette)
+                    .with_system(display_tower_panel)
+                    .with_system(update_tower_panel.after(display_tower_panel))
+                    .with_system(tower_panel_buttons),
             );
     }
 }
@@ -31,6 +43,10 @@ enum UiButtons {
     SwitchPlane,
     BuildTower,
     Cancel,
+    Tower(TowerKind),
+    Upgrade,
+    Sell,
+    Targeting,
 }
 
 impl From<UiButtons> for String {
@@ -45,6 +61,10 @@ impl From<UiButtons> for String {
             UiButtons::SwitchPlane => "Switch Plane".to_string(),
             UiButtons::BuildTower => "Build".to_string(),
             UiButtons::Cancel => "Cancel".to_string(),
+            UiButtons::Tower(kind) => kind.name().to_string(),
+            UiButtons::Upgrade => "Upgrade".to_string(),
+            UiButtons::Sell => "Sell".to_string(),
+            UiButtons::Targeting => "Target".to_string(),
         }
     }
 }
@@ -55,11 +75,35 @@ struct LiveMarker;
 #[derive(Component)]
 struct CreditsMarker;
 
+#[derive(Component)]
+struct WaveMarker;
+
+#[derive(Component)]
+struct WaveNotice;
+
+#[derive(Component)]
+struct TowerPanel;
+
+/// Line of the tower panel.
+#[derive(Component)]
+struct TowerInfo(usize);
+
+/// Is a window position over the buttons or the tower palette.
+pub(crate) fn in_ui_zone(position: Vec2, window: &Window) -> bool {
+    (position.x < 140.0 && position.y > window.height() - 260.0) || position.y < 60.0
+}
+
+/// Is a window position over the tower panel, when it's displayed.
+pub(crate) fn in_tower_panel(position: Vec2, window: &Window) -> bool {
+    position.x > window.width() - 220.0 && position.y > window.height() - 260.0
+}
+
 fn setup(
     mut commands: Commands,
     ui_handles: Res<UiAssets>,
     buttons: Res<Assets<crate::ui_helper::button::Button>>,
     stats: Res<Stats>,
+    balance: Res<Balance>,
 ) {
     info!("loading UI");
 
@@ -109,6 +153,21 @@ fn setup(
         30.,
     );
 
+    let palette_buttons = TowerKind::ALL
+        .iter()
+        .map(|kind| {
+            button.add(
+                &mut commands,
+                110.,
+                40.,
+                UiRect::all(Val::Px(5.)),
+                font.clone(),
+                UiButtons::Tower(*kind),
+                18.,
+            )
+        })
+        .collect::<Vec<_>>();
+
     let lives_text = commands
         .spawn_bundle(TextBundle {
             style: Style {
@@ -164,7 +223,7 @@ fn setup(
                 TextSection {
                     value: format!("{}", stats.credits),
                     style: TextStyle {
-                        font,
+                        font: font.clone(),
                         color: crate::ui_helper::ColorScheme::TEXT,
                         font_size: 20.,
                         ..Default::default()
@@ -175,6 +234,39 @@ fn setup(
         })
         .insert(CreditsMarker)
         .id();
+    let wave_text = commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                size: Size {
+                    height: Val::Px(20.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::from_sections([
+                TextSection {
+                    value: "wave: ".to_string(),
+                    style: TextStyle {
+                        font: font.clone(),
+                        color: crate::ui_helper::ColorScheme::TEXT,
+                        font_size: 20.,
+                        ..Default::default()
+                    },
+                },
+                TextSection {
+                    value: "0".to_string(),
+                    style: TextStyle {
+                        font: font.clone(),
+                        color: crate::ui_helper::ColorScheme::TEXT,
+                        font_size: 20.,
+                        ..Default::default()
+                    },
+                },
+            ]),
+            ..Default::default()
+        })
+        .insert(WaveMarker)
+        .id();
 
     let inner_content = commands
         .spawn_bundle(NodeBundle {
@@ -185,12 +277,12 @@ fn setup(
             },
             ..Default::default()
         })
-        .push_children(&[lives_text, credits_text])
+        .push_children(&[lives_text, credits_text, wave_text])
         .id();
     let panel = commands
         .spawn_bundle(bevy_ninepatch::NinePatchBundle {
             style: Style {
-                size: Size::new(Val::Px(120.), Val::Px(80.)),
+                size: Size::new(Val::Px(120.), Val::Px(100.)),
                 align_content: AlignContent::Stretch,
                 flex_direction: FlexDirection::ColumnReverse,
                 ..Default::default()
@@ -250,6 +342,66 @@ fn setup(
                         .push_children(&[zoom_in_button, zoom_out_button]);
                 })
                 .push_children(&[build_button, switch_button]);
+            commands
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position: UiRect {
+                            bottom: Val::Px(5.0),
+                            ..default()
+                        },
+                        size: Size::new(Val::Percent(100.0), Val::Px(50.0)),
+                        flex_direction: FlexDirection::Row,
+                        justify_content: JustifyContent::Center,
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    },
+                    color: UiColor(Color::NONE),
+                    ..default()
+                })
+                .push_children(&palette_buttons);
+            commands
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position: UiRect {
+                            top: Val::Px(20.0),
+                            ..default()
+                        },
+                        size: Size::new(Val::Percent(100.0), Val::Px(40.0)),
+                        justify_content: JustifyContent::Center,
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    },
+                    color: UiColor(Color::NONE),
+                    ..default()
+                })
+                .with_children(|builder| {
+                    builder
+                        .spawn_bundle(TextBundle {
+                            text: Text::from_sections([
+                                TextSection {
+                                    value: "".to_string(),
+                                    style: TextStyle {
+                                        font: font.clone(),
+                                        color: crate::ui_helper::ColorScheme::TEXT,
+                                        font_size: 30.,
+                                    },
+                                },
+                                TextSection {
+                                    value: format!(
+                                        "next wave in {}",
+                                        balance.waves.build_phase.ceil()
+                                    ),
+                                    style: TextStyle {
+                                        font,
+                                        color: crate::ui_helper::ColorScheme::TEXT,
+                                        font_size: 30.,
+                                    },
+                                },
+                            ]),
+                            ..Default::default()
+                        })
+                        .insert(WaveNotice);
+                });
         });
 }
 
@@ -263,6 +415,7 @@ fn button_system(
     time: Res<Time>,
     mut playing_state: ResMut<State<PlayingState>>,
     mut building: ResMut<IsBuilding>,
+    mut selected: ResMut<SelectedTower>,
 ) {
     if *playing_state.current() != PlayingState::SwitchingPlane {
         for (interaction, button_id, changed) in interaction_query.iter() {
@@ -306,6 +459,18 @@ fn button_system(
                             }
                         }
                     }
+                    (UiButtons::Tower(kind), true) => {
+                        selected.0 = kind;
+                        if !building.0 {
+                            playing_state.set(PlayingState::Building).unwrap();
+                            for (mut text, button) in &mut text_query {
+                                if button.0 == UiButtons::BuildTower {
+                                    text.sections[0].value = UiButtons::Cancel.into();
+                                    building.0 = true;
+                                }
+                            }
+                        }
+                    }
                     _ => (),
                 }
             }
@@ -313,11 +478,258 @@ fn button_system(
     }
 }
 
+fn display_palette(
+    selected: Res<SelectedTower>,
+    balance: Res<Balance>,
+    mut texts: Query<(&mut Text, &ButtonText<UiButtons>)>,
+) {
+    for (mut text, button) in &mut texts {
+        if let UiButtons::Tower(kind) = button.0 {
+            // costs can change while playing when the balance file is edited
+            let label = format!("{} {}", kind.name(), balance.tower(kind).cost);
+            if text.sections[0].value != label {
+                text.sections[0].value = label;
+            }
+            text.sections[0].style.color = if kind == selected.0 {
+                crate::ui_helper::ColorScheme::TEXT
+            } else {
+                crate::ui_helper::ColorScheme::TEXT_DARK
+            };
+        }
+    }
+}
+
 fn update_ui(
     stats: Res<Stats>,
+    director: Res<WaveDirector>,
     mut live_text: Query<&mut Text, (With<LiveMarker>, Without<CreditsMarker>)>,
-    mut credits_text: Query<&mut Text, With<CreditsMarker>>,
+    mut credits_text: Query<&mut Text, (With<CreditsMarker>, Without<WaveMarker>)>,
+    mut wave_text: Query<&mut Text, (With<WaveMarker>, Without<LiveMarker>)>,
 ) {
     live_text.single_mut().sections[1].value = format!("{}", stats.life);
     credits_text.single_mut().sections[1].value = format!("{}", stats.credits);
+    wave_text.single_mut().sections[1].value = format!("{}", director.wave);
+}
+
+fn display_waves(
+    mut wave_events: EventReader<WaveEvent>,
+    mut notice: Query<&mut Text, With<WaveNotice>>,
+) {
+    // keep the events until the notice is spawned
+    let mut notice = match notice.get_single_mut() {
+        Ok(notice) => notice,
+        Err(_) => return,
+    };
+    for event in wave_events.iter() {
+        match event {
+            WaveEvent::Countdown(seconds) => {
+                notice.sections[1].value = format!("next wave in {}", seconds);
+            }
+            WaveEvent::Started(wave) => {
+                notice.sections[0].value = format!("wave {}", wave);
+                notice.sections[1].value = "".to_string();
+            }
+            WaveEvent::Cleared(wave) => {
+                notice.sections[0].value = format!("wave {} cleared! ", wave);
+            }
+        }
+    }
+}
+
+fn display_tower_panel(
+    mut commands: Commands,
+    inspected: Res<InspectedTower>,
+    ui_handles: Res<UiAssets>,
+    buttons: Res<Assets<crate::ui_helper::button::Button>>,
+    panel: Query<Entity, With<TowerPanel>>,
+) {
+    if !inspected.is_changed() {
+        return;
+    }
+    for entity in &panel {
+        commands.entity(entity).despawn_recursive();
+    }
+    if inspected.0.is_none() {
+        return;
+    }
+
+    let button = buttons.get(&ui_handles.button_handle.clone_weak()).unwrap();
+    let font = ui_handles.font_sub.clone_weak();
+    let panel_handles = ui_handles.panel_handle.clone_weak();
+
+    let lines = (0..7)
+        .map(|line| {
+            commands
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        size: Size {
+                            height: Val::Px(20.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            color: crate::ui_helper::ColorScheme::TEXT,
+                            font_size: 20.,
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(TowerInfo(line))
+                .id()
+        })
+        .collect::<Vec<_>>();
+    let upgrade_button = button.add(
+        &mut commands,
+        60.,
+        35.,
+        UiRect::all(Val::Auto),
+        font.clone(),
+        UiButtons::Upgrade,
+        15.,
+    );
+    let sell_button = button.add(
+        &mut commands,
+        60.,
+        35.,
+        UiRect::all(Val::Auto),
+        font.clone(),
+        UiButtons::Sell,
+        15.,
+    );
+    let targeting_button = button.add(
+        &mut commands,
+        60.,
+        35.,
+        UiRect::all(Val::Auto),
+        font,
+        UiButtons::Targeting,
+        15.,
+    );
+    let button_row = commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                justify_content: JustifyContent::SpaceAround,
+                ..default()
+            },
+            color: UiColor(Color::NONE),
+            ..default()
+        })
+        .push_children(&[upgrade_button, sell_button, targeting_button])
+        .id();
+
+    let inner_content = commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor(Color::NONE),
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .push_children(&lines)
+        .push_children(&[button_row])
+        .id();
+    commands
+        .spawn_bundle(bevy_ninepatch::NinePatchBundle {
+            style: Style {
+                position: UiRect {
+                    right: Val::Px(20.0),
+                    top: Val::Px(20.0),
+                    ..default()
+                },
+                size: Size::new(Val::Px(200.), Val::Px(220.)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            nine_patch_data: bevy_ninepatch::NinePatchData::with_single_content(
+                panel_handles.1,
+                panel_handles.0,
+                inner_content,
+            ),
+            ..Default::default()
+        })
+        .insert_bundle((TowerPanel, GameTag));
+}
+
+fn update_tower_panel(
+    mut inspected: ResMut<InspectedTower>,
+    towers: Query<&Tower>,
+    mut lines: Query<(&mut Text, &TowerInfo)>,
+    balance: Res<Balance>,
+) {
+    let entity = match inspected.0 {
+        Some(entity) => entity,
+        None => return,
+    };
+    let tower = match towers.get(entity) {
+        Ok(tower) => tower,
+        Err(_) => {
+            // the tower is gone
+            inspected.0 = None;
+            return;
+        }
+    };
+    for (mut text, line) in &mut lines {
+        text.sections[0].value = match line.0 {
+            0 => format!("{} level {}", tower.kind.name(), tower.level),
+            1 => format!(
+                "health: {:.0}/{:.0}",
+                tower.health.max(0.0),
+                tower.max_health
+            ),
+            2 => format!("kills: {}", tower.kills),
+            3 => format!("damage: {:.0}", tower.damage),
+            4 => match tower.upgrade_cost(&balance) {
+                Some(cost) => format!("upgrade: {}", cost),
+                None => "upgrade: max".to_string(),
+            },
+            5 => format!("sell: {}", tower.refund(&balance)),
+            _ => format!("target: {}", tower.targeting.name()),
+        };
+    }
+}
+
+fn tower_panel_buttons(
+    interaction_query: Query<(&Interaction, &ButtonId<UiButtons>), Changed<Interaction>>,
+    inspected: Res<InspectedTower>,
+    mut towers: Query<&mut Tower>,
+    mut stats: ResMut<Stats>,
+    mut sells: EventWriter<SellTower>,
+    balance: Res<Balance>,
+) {
+    let entity = match inspected.0 {
+        Some(entity) => entity,
+        None => return,
+    };
+    for (interaction, button_id) in &interaction_query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match button_id.0 {
+            UiButtons::Upgrade => {
+                if let Ok(mut tower) = towers.get_mut(entity) {
+                    match tower.upgrade_cost(&balance) {
+                        Some(cost) if stats.credits >= cost => {
+                            stats.credits -= cost;
+                            tower.upgrade(&balance);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            UiButtons::Sell => sells.send(SellTower(entity)),
+            UiButtons::Targeting => {
+                if let Ok(mut tower) = towers.get_mut(entity) {
+                    tower.targeting = tower.targeting.next();
+                }
+            }
+            _ => (),
+        }
+    }
 }
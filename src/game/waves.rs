@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::GameState;
+
+use super::{
+    balance::Balance,
+    nests::{ZombieNest, ZombieRng},
+    timestep::{playing, SimulationStage, SimulationTime},
+    zombies::{IdleZombie, Pathless, Zombie},
+};
+
+pub(crate) struct Plugin;
+
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WaveDirector>()
+            .add_event::<WaveEvent>()
+            .add_event::<NestSpawn>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup))
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(playing)
+                    .with_system(direct_waves),
+            );
+    }
+}
+
+/// Schedules waves of zombies, with a build phase between them.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct WaveDirector {
+    /// Number of the current wave, `0` before the first one.
+    pub(crate) wave: u32,
+    phase: WavePhase,
+    /// Nests taking part in the current wave.
+    nests: Vec<((i32, i32), (i32, i32))>,
+    /// Spawns left for each nest in the current wave.
+    remaining: u32,
+    /// Seconds until the next spawn, the next wave, or the end of the fight, depending on the
+    /// phase.
+    countdown: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+enum WavePhase {
+    Building,
+    Spawning,
+    Fighting,
+}
+
+impl Default for WaveDirector {
+    fn default() -> Self {
+        Self {
+            wave: 0,
+            phase: WavePhase::Building,
+            nests: vec![],
+            remaining: 0,
+            countdown: 0.0,
+        }
+    }
+}
+
+impl WaveDirector {
+    /// Event describing the current state, to refresh the UI after loading a game.
+    pub(crate) fn status(&self) -> WaveEvent {
+        match self.phase {
+            WavePhase::Building => WaveEvent::Countdown(self.countdown.ceil() as u32),
+            WavePhase::Spawning | WavePhase::Fighting => WaveEvent::Started(self.wave),
+        }
+    }
+}
+
+/// Sent when something changes in the waves, for the UI.
+pub(crate) enum WaveEvent {
+    /// Seconds left before the next wave starts.
+    Countdown(u32),
+    Started(u32),
+    Cleared(u32),
+}
+
+/// Sent when a nest should spawn zombies.
+pub(crate) struct NestSpawn {
+    pub(crate) map: IVec2,
+    pub(crate) lot: IVec2,
+}
+
+fn setup(mut commands: Commands, balance: Res<Balance>) {
+    commands.insert_resource(WaveDirector {
+        countdown: balance.waves.build_phase,
+        ..default()
+    });
+}
+
+pub(crate) fn direct_waves(
+    mut director: ResMut<WaveDirector>,
+    nests: Query<&ZombieNest>,
+    // zombies without a path may never reach the crystal
+    zombies: Query<(), (Or<(With<Zombie>, With<IdleZombie>)>, Without<Pathless>)>,
+    time: Res<SimulationTime>,
+    mut rng: ResMut<ZombieRng>,
+    mut wave_events: EventWriter<WaveEvent>,
+    mut spawns: EventWriter<NestSpawn>,
+    balance: Res<Balance>,
+) {
+    let stats = &balance.waves;
+    match director.phase {
+        WavePhase::Building => {
+            let before = director.countdown.ceil() as u32;
+            director.countdown -= time.delta_seconds();
+            if director.countdown > 0.0 {
+                let after = director.countdown.ceil() as u32;
+                if after != before {
+                    wave_events.send(WaveEvent::Countdown(after));
+                }
+                return;
+            }
+            // wait for the first nests to be generated
+            if nests.is_empty() {
+                return;
+            }
+            director.wave += 1;
+            let wave = director.wave;
+            let mut all_nests = nests
+                .iter()
+                .map(|nest| ((nest.map.x, nest.map.y), (nest.lot.x, nest.lot.y)))
+                .collect::<Vec<_>>();
+            // sorted so that the choice only depends on the seed
+            all_nests.sort_unstable();
+            director.nests = all_nests
+                .choose_multiple(&mut rng.0, stats.nest_count(wave))
+                .copied()
+                .collect();
+            director.remaining = stats.spawns_per_nest(wave);
+            director.countdown = 0.0;
+            director.phase = WavePhase::Spawning;
+            info!("wave {} with {} nests", wave, director.nests.len());
+            wave_events.send(WaveEvent::Started(wave));
+        }
+        WavePhase::Spawning => {
+            director.countdown -= time.delta_seconds();
+            if director.countdown > 0.0 {
+                return;
+            }
+            spawns.send_batch(director.nests.iter().map(|(map, lot)| NestSpawn {
+                map: IVec2::new(map.0, map.1),
+                lot: IVec2::new(lot.0, lot.1),
+            }));
+            director.remaining -= 1;
+            director.countdown = stats.spawn_interval(director.wave);
+            if director.remaining == 0 {
+                director.phase = WavePhase::Fighting;
+                director.countdown = stats.max_fight;
+            }
+        }
+        WavePhase::Fighting => {
+            director.countdown -= time.delta_seconds();
+            if zombies.is_empty() || director.countdown <= 0.0 {
+                wave_events.send(WaveEvent::Cleared(director.wave));
+                director.phase = WavePhase::Building;
+                director.countdown = stats.build_phase;
+                wave_events.send(WaveEvent::Countdown(stats.build_phase.ceil() as u32));
+            }
+        }
+    }
+}
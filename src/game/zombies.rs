@@ -2,23 +2,34 @@ use std::f32::consts::PI;
 
 use bevy::prelude::*;
 
-use crate::{
-    game::terrain_spawner::{map_to_world, world_to_map},
-    GameState,
-};
+use crate::game::terrain_spawner::{map_to_world, world_to_map};
 
-use super::{stats::Stats, terra::Plane, terrain_spawner::Pathfinding, PlayingState};
+use super::{
+    effects::{tick_effects, StatusEffects},
+    flow_field::{update_flow_fields, FlowFields, PathfindingMode},
+    heightmap::TerrainConfig,
+    stats::Stats,
+    terra::Plane,
+    terrain_spawner::{NavmeshChanged, Pathfinding},
+    timestep::{playing, SimulationStage, SimulationTime},
+    PlayingState,
+};
 
 pub(crate) struct Plugin;
 
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(move_zombies.before(death))
-                .with_system(refresh_zombie_path.before(move_zombies).before(death))
-                .with_system(death),
-        );
+        app.init_resource::<PathfindingMode>()
+            .init_resource::<FlowFields>()
+            .add_system_set_to_stage(
+                SimulationStage,
+                SystemSet::new()
+                    .with_run_criteria(playing)
+                    .with_system(move_zombies.before(death))
+                    .with_system(update_flow_fields.before(refresh_zombie_path))
+                    .with_system(refresh_zombie_path.before(move_zombies).before(death))
+                    .with_system(death.after(tick_effects)),
+            );
     }
 }
 
@@ -29,72 +40,112 @@ pub(crate) struct IdleZombie {
     pub(crate) speed: f32,
 }
 
+/// Idle zombie for which no path to the crystal was found, waiting for the navigation of its
+/// plane to change before looking again.
+#[derive(Component)]
+pub(crate) struct Pathless;
+
+/// What a zombie is worth when killed, and what it costs when reaching the crystal.
+#[derive(Component, Clone)]
+pub(crate) struct ZombieKind {
+    pub(crate) archetype: String,
+    pub(crate) reward: u32,
+    pub(crate) damage: u32,
+    /// Damage per second done to a tower next to it.
+    pub(crate) tower_damage: f32,
+    /// Life when it spawned.
+    pub(crate) max_life: f32,
+}
+
 #[derive(Component)]
 pub(crate) struct Zombie {
-    pub(crate) path: polyanya::Path,
+    pub(crate) path: Vec<Vec2>,
     pub(crate) current_path: usize,
     pub(crate) plane: Plane,
     pub(crate) life: f32,
     pub(crate) speed: f32,
 }
 
-fn move_zombies(
-    mut commands: Commands,
-    mut zombies: Query<(Entity, &mut Transform, &Zombie)>,
-    time: Res<Time>,
-    mut stats: ResMut<Stats>,
-    playing_state: Res<State<PlayingState>>,
-) {
-    if *playing_state.current() != PlayingState::SwitchingPlane {
-        for (entity, mut transform, zombie) in &mut zombies {
-            let tr = transform.translation;
-            if zombie.current_path < zombie.path.path.len() {
-                let target = zombie.path.path[zombie.current_path];
-                let target = Vec3::new(target.x, 0.0, target.y);
-                transform.look_at(target, Vec3::Y);
-                transform.rotate(Quat::from_rotation_y(PI));
-                transform.translation +=
-                    (target - tr).normalize() * time.delta_seconds() * (0.2 + zombie.speed);
-                if transform.translation.distance_squared(Vec3::ZERO) < 0.01 {
-                    commands.entity(entity).despawn_recursive();
-                    if let Some(life) = stats.life.checked_sub(1) {
-                        stats.life = life;
-                    }
-                }
-            }
+impl Zombie {
+    /// Distance left to walk to the crystal from `position`.
+    pub(crate) fn remaining_distance(
+        &self,
+        position: Vec2,
+        mode: PathfindingMode,
+        flow_fields: &FlowFields,
+        config: &TerrainConfig,
+    ) -> f32 {
+        let mut previous = position;
+        let mut distance = 0.0;
+        for waypoint in self.path.iter().skip(self.current_path) {
+            distance += previous.distance(*waypoint);
+            previous = *waypoint;
         }
+        // with flow fields, the path only holds the next waypoint
+        if mode == PathfindingMode::FlowField {
+            distance += flow_fields
+                .plane(self.plane)
+                .distance(previous, config)
+                .unwrap_or_default();
+        }
+        distance
     }
 }
 
-fn refresh_zombie_path(
+/// Walk zombies along their path, going to the next waypoint when they reach one.
+pub(crate) fn move_zombies(
     mut commands: Commands,
-    idle_zombies: Query<(Entity, &Transform, &IdleZombie)>,
-    mut zombies: Query<(Entity, &Transform, &mut Zombie), Without<IdleZombie>>,
-    pathfinding: Res<Pathfinding>,
+    mut zombies: Query<(
+        Entity,
+        &mut Transform,
+        &mut Zombie,
+        &ZombieKind,
+        Option<&StatusEffects>,
+    )>,
+    time: Res<SimulationTime>,
+    mut stats: ResMut<Stats>,
+    playing_state: Res<State<PlayingState>>,
+    flow_fields: Res<FlowFields>,
+    mode: Res<PathfindingMode>,
+    config: Res<TerrainConfig>,
 ) {
-    let mut max_per_turn = 5;
-    for (zombie, transform, idle) in &idle_zombies {
-        let map = world_to_map(Vec2::new(transform.translation.x, transform.translation.z));
-        let world = map_to_world(map);
-        let path = pathfinding.mesh.path(world, Vec2::ZERO);
-        if !path.path.is_empty() {
-            commands.entity(zombie).insert(Zombie {
-                path,
-                current_path: 0,
-                plane: idle.plane,
-                life: idle.life,
-                speed: idle.speed,
-            });
+    if *playing_state.current() == PlayingState::SwitchingPlane {
+        return;
+    }
+    for (entity, mut transform, mut zombie, kind, effects) in &mut zombies {
+        let factor = effects.map(|effects| effects.speed_factor()).unwrap_or(1.0);
+        let tr = transform.translation;
+        if zombie.current_path >= zombie.path.len() {
+            continue;
         }
-        commands.entity(zombie).remove::<IdleZombie>();
-
-        max_per_turn -= 1;
-        if max_per_turn == 0 {
-            return;
+        let waypoint = zombie.path[zombie.current_path];
+        let target = Vec3::new(waypoint.x, 0.0, waypoint.y);
+        transform.look_at(target, Vec3::Y);
+        transform.rotate(Quat::from_rotation_y(PI));
+        transform.translation +=
+            (target - tr).normalize() * time.delta_seconds() * (0.2 + zombie.speed) * factor;
+        if transform.translation.distance_squared(Vec3::ZERO) < 0.01 {
+            commands.entity(entity).despawn_recursive();
+            stats.life = stats.life.saturating_sub(kind.damage);
+            continue;
         }
-    }
-    if pathfinding.is_changed() {
-        for (entity, _, zombie) in &zombies {
+        if transform.translation.distance_squared(target) >= 0.01 {
+            continue;
+        }
+        zombie.current_path += 1;
+        if zombie.current_path < zombie.path.len() {
+            continue;
+        }
+        let next = match *mode {
+            PathfindingMode::NavMesh => None,
+            PathfindingMode::FlowField => flow_fields
+                .plane(zombie.plane)
+                .next_waypoint(waypoint, &config),
+        };
+        if let Some(next) = next {
+            zombie.path = vec![next];
+            zombie.current_path = 0;
+        } else {
             commands
                 .entity(entity)
                 .remove::<Zombie>()
@@ -104,32 +155,177 @@ fn refresh_zombie_path(
                     speed: zombie.speed,
                 });
         }
-    } else {
-        for (entity, transform, mut zombie) in &mut zombies {
-            let target = zombie.path.path[zombie.current_path];
-            let target = Vec3::new(target.x, 0.0, target.y);
-            if transform.translation.distance_squared(target) < 0.01 {
-                zombie.current_path += 1;
-                if zombie.current_path == zombie.path.path.len() {
+    }
+}
+
+fn refresh_zombie_path(
+    mut commands: Commands,
+    idle_zombies: Query<(Entity, &Transform, &IdleZombie, Option<&Pathless>)>,
+    zombies: Query<(Entity, &Transform, &Zombie), Without<IdleZombie>>,
+    pathfinding: Res<Pathfinding>,
+    flow_fields: Res<FlowFields>,
+    mode: Res<PathfindingMode>,
+    config: Res<TerrainConfig>,
+    mut navmesh_changes: EventReader<NavmeshChanged>,
+) {
+    let mut rebuilt = vec![];
+    let mut changed_lots = vec![];
+    for change in navmesh_changes.iter() {
+        match change {
+            NavmeshChanged::Rebuilt(plane) => rebuilt.push(*plane),
+            NavmeshChanged::Lots(plane, lots) => {
+                changed_lots.extend(lots.iter().map(|lot| (*plane, *lot)))
+            }
+        }
+    }
+
+    for (entity, _, idle, pathless) in &idle_zombies {
+        if pathless.is_some()
+            && (rebuilt.contains(&idle.plane)
+                || changed_lots.iter().any(|(plane, _)| *plane == idle.plane))
+        {
+            commands.entity(entity).remove::<Pathless>();
+        }
+    }
+
+    // only zombies whose path goes through a lot that changed need to look for a new one
+    if !rebuilt.is_empty() || !changed_lots.is_empty() {
+        for (entity, transform, zombie) in &zombies {
+            let position = Vec2::new(transform.translation.x, transform.translation.z);
+            if rebuilt.contains(&zombie.plane)
+                || path_crosses(zombie, position, &changed_lots, &config)
+            {
+                commands
+                    .entity(entity)
+                    .remove::<Zombie>()
+                    .insert(IdleZombie {
+                        plane: zombie.plane,
+                        life: zombie.life,
+                        speed: zombie.speed,
+                    });
+            }
+        }
+    }
+
+    match *mode {
+        PathfindingMode::NavMesh => {
+            for (zombie, transform, idle, _) in idle_zombies
+                .iter()
+                .filter(|(_, _, _, pathless)| pathless.is_none())
+                .take(5)
+            {
+                let map = world_to_map(
+                    Vec2::new(transform.translation.x, transform.translation.z),
+                    &config,
+                );
+                let world = map_to_world(map, &config);
+                let path = pathfinding.plane(idle.plane).mesh.path(world, Vec2::ZERO);
+                if path.path.is_empty() {
+                    commands.entity(zombie).insert(Pathless);
+                } else {
+                    commands
+                        .entity(zombie)
+                        .remove::<IdleZombie>()
+                        .insert(Zombie {
+                            path: path.path,
+                            current_path: 0,
+                            plane: idle.plane,
+                            life: idle.life,
+                            speed: idle.speed,
+                        });
+                }
+            }
+        }
+        PathfindingMode::FlowField => {
+            // sampling the field is cheap, every idle zombie can start walking right away
+            for (zombie, transform, idle, pathless) in &idle_zombies {
+                let position = Vec2::new(transform.translation.x, transform.translation.z);
+                if let Some(next) = flow_fields
+                    .plane(idle.plane)
+                    .next_waypoint(position, &config)
+                {
                     commands
-                        .entity(entity)
-                        .remove::<Zombie>()
-                        .insert(IdleZombie {
-                            plane: zombie.plane,
-                            life: zombie.life,
-                            speed: zombie.speed,
+                        .entity(zombie)
+                        .remove::<IdleZombie>()
+                        .remove::<Pathless>()
+                        .insert(Zombie {
+                            path: vec![next],
+                            current_path: 0,
+                            plane: idle.plane,
+                            life: idle.life,
+                            speed: idle.speed,
                         });
+                } else if pathless.is_none() {
+                    commands.entity(zombie).insert(Pathless);
                 }
             }
         }
     }
 }
 
-fn death(mut commands: Commands, zombies: Query<(Entity, &Zombie)>, mut stats: ResMut<Stats>) {
-    for (entity, zombie) in &zombies {
-        if zombie.life < 0.0 {
+/// Whether the remaining path of a zombie at `position` goes through one of the `lots`.
+fn path_crosses(
+    zombie: &Zombie,
+    position: Vec2,
+    lots: &[(Plane, (IVec2, IVec2))],
+    config: &TerrainConfig,
+) -> bool {
+    let half_size = 0.5 / config.lots_per_tile as f32;
+    let mut from = position;
+    for to in zombie.path.iter().skip(zombie.current_path) {
+        if lots.iter().any(|(plane, lot)| {
+            *plane == zombie.plane
+                && segment_crosses_square(from, *to, map_to_world(*lot, config), half_size)
+        }) {
+            return true;
+        }
+        from = *to;
+    }
+    false
+}
+
+fn segment_crosses_square(from: Vec2, to: Vec2, center: Vec2, half_size: f32) -> bool {
+    let min = center - Vec2::splat(half_size);
+    let max = center + Vec2::splat(half_size);
+    let direction = to - from;
+    let (mut enter, mut exit) = (0.0_f32, 1.0_f32);
+    for axis in 0..2 {
+        if direction[axis].abs() < f32::EPSILON {
+            if from[axis] < min[axis] || from[axis] > max[axis] {
+                return false;
+            }
+        } else {
+            let t1 = (min[axis] - from[axis]) / direction[axis];
+            let t2 = (max[axis] - from[axis]) / direction[axis];
+            enter = enter.max(t1.min(t2));
+            exit = exit.min(t1.max(t2));
+            if enter > exit {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Whether a zombie with this much life left should die, and count as a kill.
+pub(crate) fn is_dead(life: f32) -> bool {
+    life <= 0.0
+}
+
+pub(crate) fn death(
+    mut commands: Commands,
+    zombies: Query<(Entity, Option<&Zombie>, Option<&IdleZombie>, &ZombieKind)>,
+    mut stats: ResMut<Stats>,
+) {
+    for (entity, zombie, idle, kind) in &zombies {
+        // burning zombies can die while waiting for a path
+        let dead = zombie
+            .map(|zombie| zombie.life)
+            .or_else(|| idle.map(|idle| idle.life))
+            .map_or(false, is_dead);
+        if dead {
             commands.entity(entity).despawn_recursive();
-            stats.credits += 5;
+            stats.credits += kind.reward;
             stats.killed += 1;
         }
     }
@@ -0,0 +1,272 @@
+use std::{path::PathBuf, time::Duration};
+
+use bevy::{
+    asset::AssetPlugin, core::CorePlugin, hierarchy::HierarchyPlugin, input::InputPlugin,
+    prelude::*, transform::TransformPlugin, window::WindowPlugin,
+};
+
+use crate::{
+    arg_value,
+    assets::{BuildingAssets, SceneryAssets, ZombieAssets},
+    game::{
+        ai::AiPlayer,
+        archetypes::ZombieArchetypes,
+        balance::Balance,
+        difficulty::Difficulty,
+        flow_field::PathfindingMode,
+        heightmap::TerrainConfig,
+        stats::{GameTag, Stats},
+        terra::RunSeed,
+        terrain_spawner::EmptyLot,
+        timestep::STEP,
+        towers::Tower,
+        waves::WaveDirector,
+        SimulationPlugin,
+    },
+    GameState,
+};
+
+/// How a headless run ended.
+struct RunResult {
+    seed: u64,
+    difficulty: Difficulty,
+    wave: u32,
+    survived: f32,
+    killed: u32,
+    credits: u32,
+    towers: usize,
+    lost: bool,
+    /// Stopped because time stood still for [`MAX_STALLED_UPDATES`] updates.
+    stuck: bool,
+}
+
+/// Updates in a row without time going forward, waiting for tiles to be generated, before a
+/// run is given up.
+const MAX_STALLED_UPDATES: u32 = 10_000;
+
+enum Format {
+    Csv,
+    Json,
+}
+
+/// Let the AI play `runs` games without a window, and print how each one went.
+///
+/// Options are read from the command line: `--seed` for the first run, each next run using the
+/// next seed, `--difficulty`, `--max-secs` to stop a run that lasts too long, `--format` to
+/// pick between `csv` and `json` and `--assets` for the folder holding the game files, the one of
+/// the repository by default.
+pub(crate) fn run(
+    runs: u64,
+    terrain_config: TerrainConfig,
+    pathfinding_mode: PathfindingMode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let seed = match arg_value("--seed") {
+        Some(seed) => seed.parse()?,
+        None => RunSeed::random().0,
+    };
+    let difficulty = match arg_value("--difficulty") {
+        Some(name) => Difficulty::from_name(&name).ok_or(format!("unknown difficulty {}", name))?,
+        None => Difficulty::default(),
+    };
+    let max_secs: f32 = match arg_value("--max-secs") {
+        Some(secs) => secs.parse()?,
+        None => 3600.0,
+    };
+    let format = match arg_value("--format").as_deref() {
+        Some("json") => Format::Json,
+        _ => Format::Csv,
+    };
+    let assets = match arg_value("--assets") {
+        Some(assets) => PathBuf::from(assets),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets"),
+    };
+    let balance = Balance::from_ron(&std::fs::read(assets.join("game.balance.ron"))?)?;
+    let archetypes = std::fs::read(assets.join("zombies/archetypes.zombies.ron"))?;
+
+    if let Format::Csv = format {
+        println!("seed,difficulty,wave,survived_secs,killed,credits,towers,lost,stuck");
+    }
+    for run in 0..runs {
+        let result = simulate(
+            seed.wrapping_add(run),
+            difficulty,
+            max_secs,
+            terrain_config,
+            pathfinding_mode,
+            &balance,
+            &archetypes,
+        )?;
+        match format {
+            Format::Csv => println!(
+                "{},{},{},{:.1},{},{},{},{},{}",
+                result.seed,
+                result.difficulty.name(),
+                result.wave,
+                result.survived,
+                result.killed,
+                result.credits,
+                result.towers,
+                result.lost,
+                result.stuck
+            ),
+            Format::Json => println!(
+                r#"{{"seed":{},"difficulty":"{}","wave":{},"survived_secs":{:.1},"killed":{},"credits":{},"towers":{},"lost":{},"stuck":{}}}"#,
+                result.seed,
+                result.difficulty.name(),
+                result.wave,
+                result.survived,
+                result.killed,
+                result.credits,
+                result.towers,
+                result.lost,
+                result.stuck
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Play one game until it's lost, has lasted `max_secs`, or is stuck generating tiles.
+fn simulate(
+    seed: u64,
+    difficulty: Difficulty,
+    max_secs: f32,
+    terrain_config: TerrainConfig,
+    pathfinding_mode: PathfindingMode,
+    balance: &Balance,
+    archetypes: &[u8],
+) -> Result<RunResult, Box<dyn std::error::Error>> {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin::default())
+        .add_plugin(AssetPlugin)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(WindowPlugin {
+            add_primary_window: false,
+            exit_on_all_closed: false,
+            ..default()
+        })
+        .add_asset::<Mesh>()
+        .add_asset::<Image>()
+        .add_asset::<StandardMaterial>()
+        .add_asset::<Balance>()
+        .add_asset::<ZombieArchetypes>()
+        .init_resource::<Time>()
+        .add_system_to_stage(CoreStage::First, step_time)
+        .insert_resource(AiPlayer { attract: false })
+        .insert_resource(RunSeed(seed))
+        .insert_resource(difficulty)
+        .insert_resource(terrain_config)
+        .insert_resource(pathfinding_mode)
+        .insert_resource(balance.clone())
+        .insert_resource(BuildingAssets {
+            crystal: default(),
+            material_tower: default(),
+            ethereal_tower: default(),
+            block: default(),
+            coffin: default(),
+            coffin_old: default(),
+        })
+        .insert_resource(SceneryAssets {
+            tree: default(),
+            trunk: default(),
+            bench: default(),
+            bench_damaged: default(),
+            rock: default(),
+            missile_mesh: default(),
+            missile_material: default(),
+        });
+    let archetypes = ZombieArchetypes::from_ron(archetypes, |_| default())?;
+    let archetypes = app
+        .world
+        .resource_mut::<Assets<ZombieArchetypes>>()
+        .add(archetypes);
+    app.insert_resource(ZombieAssets {
+        animations: default(),
+        zombie: default(),
+        archetypes,
+    })
+    .add_state(GameState::Playing)
+    .add_plugin(SimulationPlugin)
+    .add_system_set(SystemSet::on_update(GameState::Playing).with_system(reveal_lots));
+    // switching plane fades the light
+    app.world.spawn().insert(DirectionalLight::default());
+
+    let mut last_time = Duration::ZERO;
+    let mut stalled = 0;
+    loop {
+        app.update();
+        let lost = *app.world.resource::<State<GameState>>().current() == GameState::Lost;
+        let stats = app.world.resource::<Stats>();
+        if stats.time.elapsed() == last_time {
+            stalled += 1;
+        } else {
+            last_time = stats.time.elapsed();
+            stalled = 0;
+        }
+        let stuck = stalled >= MAX_STALLED_UPDATES;
+        if stuck {
+            eprintln!(
+                "run with seed {} stuck after {:.1}s, time didn't go forward for {} updates",
+                seed,
+                stats.time.elapsed_secs(),
+                stalled
+            );
+        }
+        if lost || stuck || stats.time.elapsed_secs() >= max_secs {
+            let towers = app
+                .world
+                .query_filtered::<(), With<Tower>>()
+                .iter(&app.world)
+                .count();
+            let stats = app.world.resource::<Stats>();
+            return Ok(RunResult {
+                seed,
+                difficulty,
+                wave: app.world.resource::<WaveDirector>().wave,
+                survived: stats.time.elapsed_secs(),
+                killed: stats.killed,
+                credits: stats.credits,
+                towers,
+                lost,
+                stuck,
+            });
+        }
+    }
+}
+
+/// Advance time by a fixed step, so that a run doesn't depend on the speed of the machine. Time
+/// stands still while tiles are being generated.
+fn step_time(mut time: ResMut<Time>, loading: Query<(), With<EmptyLot>>) {
+    let last = time.last_update().unwrap_or_else(|| time.startup());
+    if loading.is_empty() {
+        time.update_with_instant(last + Duration::from_secs_f64(STEP));
+    } else {
+        time.update_with_instant(last);
+    }
+}
+
+/// Generate tiles around the crystal, further away as time goes, like the camera reveals them.
+fn reveal_lots(mut commands: Commands, stats: Res<Stats>, mut span: Local<Option<i32>>) {
+    let wanted = 3 + stats.time.elapsed().as_secs() as i32 / 40;
+    let previous = span.unwrap_or(-1);
+    if previous >= wanted {
+        return;
+    }
+    for i in -wanted..=wanted {
+        for j in -wanted..=wanted {
+            if i.abs() <= previous && j.abs() <= previous {
+                continue;
+            }
+            let position = IVec2::new(i, j);
+            commands.spawn_bundle((
+                EmptyLot::new(position, true),
+                Transform::from_xyz(position.x as f32, 0.0, position.y as f32),
+                GlobalTransform::identity(),
+                GameTag,
+            ));
+        }
+    }
+    *span = Some(wanted);
+}
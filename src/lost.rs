@@ -5,7 +5,9 @@ use bevy_jornet::{Leaderboard, Score};
 
 use crate::{
     assets::{CloneWeak, UiAssets},
-    game::stats::Stats,
+    game::{
+        ai::AiPlayer, difficulty::Difficulty, stats::Stats, terra::TerraNoises, waves::WaveDirector,
+    },
     ui_helper::ColorScheme,
 };
 
@@ -39,6 +41,10 @@ fn setup(
     ui_handles: Res<UiAssets>,
     stats: Res<Stats>,
     leaderboard: Res<Leaderboard>,
+    noises: Res<TerraNoises>,
+    director: Res<WaveDirector>,
+    difficulty: Res<Difficulty>,
+    ai: Option<Res<AiPlayer>>,
 ) {
     info!("Loading screen");
 
@@ -46,7 +52,10 @@ fn setup(
         done: Timer::from_seconds(20.0, false),
     });
 
-    leaderboard.send_score(stats.killed as f32);
+    // games played by the AI don't go on the leaderboard
+    if ai.is_none() {
+        leaderboard.send_score_with_meta(director.wave as f32, difficulty.name());
+    }
     leaderboard.refresh_leaderboard();
 
     let panel_handles = ui_handles.panel_handle.clone_weak();
@@ -106,7 +115,12 @@ fn setup(
                 ..Default::default()
             },
             text: Text::from_section(
-                format!("you survived {} seconds", stats.time.elapsed().as_secs()),
+                format!(
+                    "you reached wave {} in {} seconds on {}",
+                    director.wave,
+                    stats.time.elapsed().as_secs(),
+                    difficulty.name()
+                ),
                 TextStyle {
                     font: font_details.clone(),
                     color: crate::ui_helper::ColorScheme::TEXT,
@@ -138,6 +152,27 @@ fn setup(
             ..Default::default()
         })
         .id();
+    let seed = commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                size: Size {
+                    height: Val::Px(40.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::from_section(
+                format!("seed {}", noises.seed),
+                TextStyle {
+                    font: font_details.clone_weak(),
+                    color: crate::ui_helper::ColorScheme::TEXT,
+                    font_size: 20.,
+                    ..Default::default()
+                },
+            ),
+            ..Default::default()
+        })
+        .id();
 
     let inner_content = commands
         .spawn_bundle(NodeBundle {
@@ -148,7 +183,7 @@ fn setup(
             },
             ..Default::default()
         })
-        .push_children(&[time_survived, zombie_killed])
+        .push_children(&[time_survived, zombie_killed, seed])
         .id();
 
     commands
@@ -310,17 +345,18 @@ fn display_scores(
     mut commands: Commands,
     root_ui: Query<(Entity, &LeaderboardMarker)>,
     assets: Res<UiAssets>,
-    stats: Res<Stats>,
+    director: Res<WaveDirector>,
+    difficulty: Res<Difficulty>,
 ) {
     if leaderboard.is_changed() {
         let mut scores = leaderboard.get_leaderboard();
         scores.push(Score {
-            score: stats.killed as f32,
+            score: director.wave as f32,
             player: leaderboard
                 .get_player()
                 .map(|p| p.name.clone())
                 .unwrap_or_default(),
-            meta: None,
+            meta: Some(difficulty.name().to_string()),
             timestamp: "0".to_string(),
         });
         scores
@@ -333,7 +369,10 @@ fn display_scores(
                     parent.spawn_bundle(TextBundle::from_section(
                         match marker {
                             LeaderboardMarker::Score => format!("{} ", score.score),
-                            LeaderboardMarker::Player => score.player.clone(),
+                            LeaderboardMarker::Player => match &score.meta {
+                                Some(difficulty) => format!("{} ({})", score.player, difficulty),
+                                None => score.player.clone(),
+                            },
                         },
                         TextStyle {
                             font: assets.font_sub.clone_weak(),
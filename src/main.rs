@@ -7,16 +7,35 @@ use std::f32::consts::FRAC_PI_4;
 use bevy::{app::AppExit, prelude::*, render::texture::ImageSettings};
 use bevy_jornet::JornetPlugin;
 use bevy_mod_raycast::{DefaultRaycastingPlugin, RayCastSource};
-use game::terrain_spawner::RaycastSet;
+use game::{heightmap::TerrainConfig, terrain_spawner::RaycastSet};
 
 mod assets;
 mod game;
+mod headless;
 mod lost;
 mod menu;
+mod pause;
 mod splash;
 mod ui_helper;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut terrain_config = match arg_value("--grid").as_deref() {
+        Some("fine") => TerrainConfig::fine_grid(),
+        Some("coarse") => TerrainConfig::coarse_grid(),
+        _ => TerrainConfig::default(),
+    };
+    if let Some(mesh_detail) = arg_value("--mesh-detail").and_then(|detail| detail.parse().ok()) {
+        terrain_config = terrain_config.with_mesh_detail(mesh_detail);
+    }
+    let pathfinding_mode = match arg_value("--pathfinding").as_deref() {
+        Some("flow-field") => game::flow_field::PathfindingMode::FlowField,
+        _ => game::flow_field::PathfindingMode::NavMesh,
+    };
+
+    if let Some(runs) = arg_value("--headless") {
+        return headless::run(runs.parse()?, terrain_config, pathfinding_mode);
+    }
+
     let mut builder = App::new();
 
     builder
@@ -27,6 +46,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .insert_resource(ClearColor(Color::rgb(0., 0., 0.01)));
 
+    if let Some(seed) = arg_value("--seed") {
+        let seed: u64 = seed
+            .parse()
+            .map_err(|_| format!("invalid seed {}, expected a number", seed))?;
+        builder.insert_resource(menu::SeedInput(seed.to_string()));
+    }
+    // let the AI play every game, to watch it or to measure performance
+    if std::env::args().any(|arg| arg == "--ai") {
+        builder.insert_resource(game::ai::AiPlayer { attract: false });
+    }
+
+    builder
+        .insert_resource(terrain_config)
+        .insert_resource(pathfinding_mode);
+
     if cfg!(debug_assertions) {
         builder.insert_resource(bevy::log::LogSettings {
             level: bevy::log::Level::INFO,
@@ -39,6 +73,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 
+    // reload assets, including the balance file, when they change on disk
+    #[cfg(feature = "hot")]
+    builder.insert_resource(bevy::asset::AssetServerSettings {
+        watch_for_changes: true,
+        ..Default::default()
+    });
+
     builder.add_plugins_with(DefaultPlugins, |group| {
         #[cfg(feature = "bundled")]
         group.add_before::<bevy::asset::AssetPlugin, _>(bevy_embedded_assets::EmbeddedAssetPlugin);
@@ -78,6 +119,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .add_plugin(crate::splash::Plugin)
         .add_plugin(crate::menu::Plugin)
         .add_plugin(crate::game::Plugin)
+        .add_plugin(crate::pause::Plugin)
         .add_plugin(crate::lost::Plugin)
         .add_system(animate_light_direction);
 
@@ -102,11 +144,16 @@ pub(crate) enum GameState {
     Menu,
     // About,
     Playing,
-    // Paused,
+    Paused,
     Lost,
     Exit,
 }
 
+/// Value following `name` on the command line.
+fn arg_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
+
 fn general_setup(mut commands: Commands) {
     commands
         .spawn_bundle(Camera3dBundle {
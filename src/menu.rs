@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use bevy::{
     prelude::*,
+    time::Stopwatch,
     winit::{UpdateMode, WinitSettings},
 };
 
@@ -10,11 +11,21 @@ use bevy_jornet::Leaderboard;
 
 use crate::{
     assets::{CloneWeak, UiAssets, ZombieAssets},
+    game::{
+        ai::AiPlayer,
+        difficulty::Difficulty,
+        heightmap::TerrainConfig,
+        save::{read_save, PendingLoad},
+        terra::RunSeed,
+    },
     ui_helper::ColorScheme,
 };
 
 const CURRENT_STATE: crate::GameState = crate::GameState::Menu;
 
+/// Seconds without input before the AI starts playing.
+const ATTRACT_DELAY: f32 = 30.0;
+
 #[derive(Component)]
 struct ScreenTag;
 
@@ -31,10 +42,32 @@ impl Default for Screen {
     }
 }
 
+/// Seed typed in the menu, a random one is picked when empty.
+#[derive(Default)]
+pub(crate) struct SeedInput(pub(crate) String);
+
+impl SeedInput {
+    fn to_seed(&self) -> RunSeed {
+        self.0
+            .parse()
+            .map(RunSeed)
+            .unwrap_or_else(|_| RunSeed::random())
+    }
+
+    fn display(&self) -> String {
+        if self.0.is_empty() {
+            "random".to_string()
+        } else {
+            self.0.clone()
+        }
+    }
+}
+
 pub(crate) struct Plugin;
 impl bevy::app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Screen::default())
+            .init_resource::<SeedInput>()
             .add_system_set(SystemSet::on_enter(CURRENT_STATE).with_system(setup))
             .add_system_set(SystemSet::on_exit(CURRENT_STATE).with_system(tear_down))
             .add_system_set(
@@ -43,7 +76,10 @@ impl bevy::app::Plugin for Plugin {
                     .with_system(gamepad_input_system)
                     .with_system(button_system)
                     .with_system(display_menu_item_selector)
-                    .with_system(display_player_name),
+                    .with_system(display_player_name)
+                    .with_system(seed_input_system)
+                    .with_system(difficulty_system)
+                    .with_system(attract_mode),
             );
     }
 }
@@ -51,6 +87,8 @@ impl bevy::app::Plugin for Plugin {
 #[derive(Clone, Copy)]
 enum MenuButton {
     NewGame,
+    #[cfg(not(target_arch = "wasm32"))]
+    Continue,
     // About,
     #[cfg(not(target_arch = "wasm32"))]
     Quit,
@@ -60,6 +98,8 @@ impl From<MenuButton> for String {
     fn from(button: MenuButton) -> String {
         match button {
             MenuButton::NewGame => "New Game".to_string(),
+            #[cfg(not(target_arch = "wasm32"))]
+            MenuButton::Continue => "Continue".to_string(),
             // MenuButton::About => "About".to_string(),
             #[cfg(not(target_arch = "wasm32"))]
             MenuButton::Quit => "Quit".to_string(),
@@ -69,6 +109,8 @@ impl From<MenuButton> for String {
 
 const MENU_BUTTONS: &[MenuButton] = &[
     MenuButton::NewGame,
+    #[cfg(not(target_arch = "wasm32"))]
+    MenuButton::Continue,
     // MenuButton::About,
     #[cfg(not(target_arch = "wasm32"))]
     MenuButton::Quit,
@@ -86,6 +128,8 @@ fn setup(
     mut camera: Query<&mut Transform, With<Camera>>,
     mut light: Query<&mut DirectionalLight>,
     leaderboard: Res<Leaderboard>,
+    seed_input: Res<SeedInput>,
+    difficulty: Res<Difficulty>,
 ) {
     info!("Loading screen");
 
@@ -316,7 +360,7 @@ fn setup(
                         .map(|p| p.name.clone())
                         .unwrap_or_default(),
                     style: TextStyle {
-                        font: font_details,
+                        font: font_details.clone_weak(),
                         font_size: 25.0,
                         color: ColorScheme::TEXT_DARK,
                     },
@@ -334,12 +378,82 @@ fn setup(
         )
         .insert_bundle((PlayerName, ScreenTag));
 
+    commands
+        .spawn_bundle(
+            TextBundle::from_sections([
+                TextSection {
+                    value: "seed: ".to_string(),
+                    style: TextStyle {
+                        font: font_details.clone_weak(),
+                        font_size: 20.0,
+                        color: ColorScheme::TEXT_DARK,
+                    },
+                },
+                TextSection {
+                    value: seed_input.display(),
+                    style: TextStyle {
+                        font: font_details.clone_weak(),
+                        font_size: 25.0,
+                        color: ColorScheme::TEXT_DARK,
+                    },
+                },
+            ])
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert_bundle((SeedText, ScreenTag));
+
+    commands
+        .spawn_bundle(
+            TextBundle::from_sections([
+                TextSection {
+                    value: "difficulty: ".to_string(),
+                    style: TextStyle {
+                        font: font_details.clone_weak(),
+                        font_size: 20.0,
+                        color: ColorScheme::TEXT_DARK,
+                    },
+                },
+                TextSection {
+                    value: difficulty.name().to_string(),
+                    style: TextStyle {
+                        font: font_details,
+                        font_size: 25.0,
+                        color: ColorScheme::TEXT_DARK,
+                    },
+                },
+            ])
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(10.0),
+                    bottom: Val::Px(40.0),
+                    ..default()
+                },
+                ..default()
+            }),
+        )
+        .insert_bundle((DifficultyText, ScreenTag));
+
     screen.first_load = false;
 }
 
 #[derive(Component)]
 struct PlayerName;
 
+#[derive(Component)]
+struct SeedText;
+
+#[derive(Component)]
+struct DifficultyText;
+
 fn tear_down(mut commands: Commands, query: Query<Entity, With<ScreenTag>>) {
     info!("tear down");
 
@@ -356,6 +470,10 @@ fn gamepad_input_system(
     gamepad_axis: Res<Axis<GamepadAxis>>,
     mut delay: Local<Option<Timer>>,
     time: Res<Time>,
+    mut seed: ResMut<RunSeed>,
+    seed_input: Res<SeedInput>,
+    mut pending: ResMut<PendingLoad>,
+    config: Res<TerrainConfig>,
 ) {
     for gamepad in gamepads.iter() {
         if let Some(mut has_delay) = delay.take() {
@@ -395,9 +513,11 @@ fn gamepad_input_system(
         if gamepad_input.just_pressed(GamepadButton::new(*gamepad, GamepadButtonType::South)) {
             match screen.menu_selected {
                 Some(0) => {
+                    *seed = seed_input.to_seed();
                     let _ = state.set(crate::GameState::Playing);
                 }
-                // Some(1) => {
+                Some(1) => continue_game(&mut seed, &mut pending, &mut state, &config),
+                // Some(3) => {
                 //     let _ = state.set(crate::GameState::About);
                 // }
                 Some(2) => {
@@ -414,6 +534,10 @@ fn keyboard_input_system(
     mut screen: ResMut<Screen>,
     keyboard_input: Res<Input<KeyCode>>,
     mut wnds: ResMut<Windows>,
+    mut seed: ResMut<RunSeed>,
+    seed_input: Res<SeedInput>,
+    mut pending: ResMut<PendingLoad>,
+    config: Res<TerrainConfig>,
 ) {
     if keyboard_input.just_released(KeyCode::Escape) {
         #[cfg(not(target_arch = "wasm32"))]
@@ -447,9 +571,11 @@ fn keyboard_input_system(
     {
         match screen.menu_selected {
             Some(0) => {
+                *seed = seed_input.to_seed();
                 let _ = state.set(crate::GameState::Playing);
             }
-            // Some(1) => {
+            Some(1) => continue_game(&mut seed, &mut pending, &mut state, &config),
+            // Some(3) => {
             //     let _ = state.set(crate::GameState::About);
             // }
             Some(2) => {
@@ -471,6 +597,10 @@ fn button_system(
         ),
         Changed<Interaction>,
     >,
+    mut seed: ResMut<RunSeed>,
+    seed_input: Res<SeedInput>,
+    mut pending: ResMut<PendingLoad>,
+    config: Res<TerrainConfig>,
 ) {
     for (_button, interaction, button_id) in interaction_query.iter_mut() {
         match *interaction {
@@ -483,12 +613,17 @@ fn button_system(
                 //     let _ = state.set(crate::GameState::About);
                 // }
                 MenuButton::NewGame => {
+                    *seed = seed_input.to_seed();
                     let _ = state.set(crate::GameState::Playing);
                 }
+                #[cfg(not(target_arch = "wasm32"))]
+                MenuButton::Continue => continue_game(&mut seed, &mut pending, &mut state, &config),
             },
             Interaction::Hovered => match button_id.0 {
                 MenuButton::NewGame => screen.menu_selected = Some(0),
-                // MenuButton::About => screen.menu_selected = Some(1),
+                #[cfg(not(target_arch = "wasm32"))]
+                MenuButton::Continue => screen.menu_selected = Some(1),
+                // MenuButton::About => screen.menu_selected = Some(3),
                 #[cfg(not(target_arch = "wasm32"))]
                 MenuButton::Quit => screen.menu_selected = Some(2),
             },
@@ -497,6 +632,20 @@ fn button_system(
     }
 }
 
+/// Start playing the saved game, if there is one.
+fn continue_game(
+    seed: &mut RunSeed,
+    pending: &mut PendingLoad,
+    state: &mut State<crate::GameState>,
+    config: &TerrainConfig,
+) {
+    if let Some(save) = read_save(config) {
+        *seed = RunSeed(save.seed);
+        pending.0 = Some(save);
+        let _ = state.set(crate::GameState::Playing);
+    }
+}
+
 #[derive(Component)]
 struct MenuItemSelector(usize);
 
@@ -525,3 +674,86 @@ fn display_player_name(
         }
     }
 }
+
+fn seed_input_system(
+    mut seed_input: ResMut<SeedInput>,
+    mut received_characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut seed_text: Query<&mut Text, With<SeedText>>,
+) {
+    let mut changed = false;
+    for character in received_characters.iter() {
+        if character.char.is_ascii_digit() && seed_input.0.len() < 19 {
+            seed_input.0.push(character.char);
+            changed = true;
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        changed |= seed_input.0.pop().is_some();
+    }
+    if changed {
+        seed_text.single_mut().sections[1].value = seed_input.display();
+    }
+}
+
+/// Pick the difficulty with left and right, on the keyboard or a gamepad.
+fn difficulty_system(
+    mut difficulty: ResMut<Difficulty>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mut difficulty_text: Query<&mut Text, With<DifficultyText>>,
+) {
+    let pressed = |key, button| {
+        keyboard_input.just_released(key)
+            || gamepads
+                .iter()
+                .any(|gamepad| gamepad_input.just_released(GamepadButton::new(*gamepad, button)))
+    };
+    let picked = if pressed(KeyCode::Right, GamepadButtonType::DPadRight) {
+        difficulty.harder()
+    } else if pressed(KeyCode::Left, GamepadButtonType::DPadLeft) {
+        difficulty.easier()
+    } else {
+        return;
+    };
+    if picked != *difficulty {
+        *difficulty = picked;
+        difficulty_text.single_mut().sections[1].value = difficulty.name().to_string();
+    }
+}
+
+/// Let the AI play a game after some time without input, or right away when it was asked for on
+/// the command line.
+fn attract_mode(
+    mut commands: Commands,
+    mut state: ResMut<State<crate::GameState>>,
+    mut idle: Local<Stopwatch>,
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    ai: Option<Res<AiPlayer>>,
+    mut seed: ResMut<RunSeed>,
+    seed_input: Res<SeedInput>,
+) {
+    if let Some(ai) = ai {
+        // an attract mode that just ended is removed when entering the menu
+        if !ai.attract {
+            *seed = seed_input.to_seed();
+            let _ = state.set(crate::GameState::Playing);
+        }
+        return;
+    }
+    if keyboard_input.get_just_pressed().next().is_some()
+        || mouse_button_input.get_just_pressed().next().is_some()
+        || gamepad_input.get_just_pressed().next().is_some()
+    {
+        idle.reset();
+    } else if idle.tick(time.delta()).elapsed_secs() > ATTRACT_DELAY {
+        idle.reset();
+        *seed = RunSeed::random();
+        commands.insert_resource(AiPlayer { attract: true });
+        let _ = state.set(crate::GameState::Playing);
+    }
+}
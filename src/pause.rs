@@ -0,0 +1,370 @@
+use bevy::{prelude::*, window::WindowFocused};
+
+use crate::{
+    assets::{CloneWeak, UiAssets},
+    game::save::SaveRequested,
+    ui_helper::{
+        button::{ButtonId, ButtonText},
+        ColorScheme,
+    },
+};
+
+const CURRENT_STATE: crate::GameState = crate::GameState::Paused;
+
+#[derive(Component)]
+struct ScreenTag;
+
+#[derive(Component)]
+struct PanelTag;
+
+#[derive(Default)]
+struct Screen {
+    settings: bool,
+    selected: usize,
+}
+
+pub(crate) struct Plugin;
+impl bevy::app::Plugin for Plugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Screen>()
+            .add_system_set(
+                SystemSet::on_update(crate::GameState::Playing).with_system(pause_input_system),
+            )
+            .add_system_set(SystemSet::on_enter(CURRENT_STATE).with_system(setup))
+            .add_system_set(SystemSet::on_exit(CURRENT_STATE).with_system(tear_down))
+            .add_system_set(
+                SystemSet::on_update(CURRENT_STATE)
+                    .with_system(keyboard_input_system)
+                    .with_system(gamepad_input_system)
+                    .with_system(button_system)
+                    .with_system(display_selected)
+                    .with_system(display_panel.after(keyboard_input_system)),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PauseButton {
+    Resume,
+    Save,
+    Restart,
+    Settings,
+    QuitToMenu,
+    Fullscreen,
+    Back,
+}
+
+impl From<PauseButton> for String {
+    fn from(button: PauseButton) -> String {
+        match button {
+            PauseButton::Resume => "Resume".to_string(),
+            PauseButton::Save => "Save".to_string(),
+            PauseButton::Restart => "Restart".to_string(),
+            PauseButton::Settings => "Settings".to_string(),
+            PauseButton::QuitToMenu => "Quit to menu".to_string(),
+            PauseButton::Fullscreen => "Fullscreen".to_string(),
+            PauseButton::Back => "Back".to_string(),
+        }
+    }
+}
+
+const PAUSE_BUTTONS: &[PauseButton] = &[
+    PauseButton::Resume,
+    PauseButton::Save,
+    PauseButton::Restart,
+    PauseButton::Settings,
+    PauseButton::QuitToMenu,
+];
+
+const SETTINGS_BUTTONS: &[PauseButton] = &[PauseButton::Fullscreen, PauseButton::Back];
+
+impl Screen {
+    fn buttons(&self) -> &'static [PauseButton] {
+        if self.settings {
+            SETTINGS_BUTTONS
+        } else {
+            PAUSE_BUTTONS
+        }
+    }
+}
+
+fn pause_input_system(
+    mut state: ResMut<State<crate::GameState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut gamepad_input: ResMut<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut focused: EventReader<WindowFocused>,
+) {
+    let start = gamepads
+        .iter()
+        .map(|gamepad| GamepadButton::new(*gamepad, GamepadButtonType::Start))
+        .find(|button| gamepad_input.just_pressed(*button));
+    let lost_focus = focused.iter().any(|event| !event.focused);
+    if keyboard_input.just_pressed(KeyCode::Escape) || start.is_some() || lost_focus {
+        // so that the pause menu doesn't see the same press and resume right away
+        keyboard_input.reset(KeyCode::Escape);
+        if let Some(start) = start {
+            gamepad_input.reset(start);
+        }
+        let _ = state.push(CURRENT_STATE);
+    }
+}
+
+fn setup(mut commands: Commands, mut screen: ResMut<Screen>) {
+    info!("pausing game");
+
+    *screen = Screen::default();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: UiColor(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+            ..default()
+        })
+        .insert(ScreenTag);
+}
+
+/// Rebuild the panel when switching between the pause buttons and the settings.
+fn display_panel(
+    mut commands: Commands,
+    screen: Res<Screen>,
+    ui_handles: Res<UiAssets>,
+    buttons: Res<Assets<crate::ui_helper::button::Button>>,
+    overlay: Query<Entity, With<ScreenTag>>,
+    panel: Query<Entity, With<PanelTag>>,
+    mut shown: Local<Option<bool>>,
+) {
+    let overlay = match overlay.get_single() {
+        Ok(overlay) => overlay,
+        Err(_) => return,
+    };
+    if *shown == Some(screen.settings) && !panel.is_empty() {
+        return;
+    }
+    *shown = Some(screen.settings);
+    for entity in &panel {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let panel_handles = ui_handles.panel_handle.clone_weak();
+    let button_handle = ui_handles.button_handle.clone_weak();
+    let button = buttons.get(&button_handle).unwrap();
+    let font = ui_handles.font_main.clone_weak();
+
+    let title = commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                size: Size {
+                    height: Val::Px(40.),
+                    ..default()
+                },
+                margin: UiRect::all(Val::Auto),
+                ..default()
+            },
+            text: Text::from_section(
+                if screen.settings {
+                    "Settings"
+                } else {
+                    "Paused"
+                }
+                .to_string(),
+                TextStyle {
+                    font: font.clone(),
+                    color: ColorScheme::TEXT,
+                    font_size: 40.,
+                },
+            ),
+            ..default()
+        })
+        .id();
+    let button_entities = screen
+        .buttons()
+        .iter()
+        .map(|button_item| {
+            button.add(
+                &mut commands,
+                225.,
+                50.,
+                UiRect::all(Val::Px(5.)),
+                font.clone(),
+                *button_item,
+                25.,
+            )
+        })
+        .collect::<Vec<_>>();
+    let inner_content = commands
+        .spawn_bundle(NodeBundle {
+            color: UiColor(Color::NONE),
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .push_children(&[title])
+        .push_children(&button_entities)
+        .id();
+    let panel = commands
+        .spawn_bundle(bevy_ninepatch::NinePatchBundle {
+            style: Style {
+                size: Size::new(
+                    Val::Px(300.),
+                    Val::Px(90. + 60. * screen.buttons().len() as f32),
+                ),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            nine_patch_data: bevy_ninepatch::NinePatchData::with_single_content(
+                panel_handles.1,
+                panel_handles.0,
+                inner_content,
+            ),
+            ..default()
+        })
+        .insert(PanelTag)
+        .id();
+    commands.entity(overlay).push_children(&[panel]);
+}
+
+fn tear_down(mut commands: Commands, query: Query<Entity, With<ScreenTag>>) {
+    info!("resuming game");
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn activate(
+    button: PauseButton,
+    state: &mut State<crate::GameState>,
+    screen: &mut Screen,
+    windows: &mut Windows,
+    save: &mut EventWriter<SaveRequested>,
+) {
+    match button {
+        PauseButton::Resume => {
+            let _ = state.pop();
+        }
+        PauseButton::Save => save.send(SaveRequested),
+        PauseButton::Restart => {
+            let _ = state.replace(crate::GameState::Playing);
+        }
+        PauseButton::Settings => {
+            screen.settings = true;
+            screen.selected = 0;
+        }
+        PauseButton::QuitToMenu => {
+            let _ = state.replace(crate::GameState::Menu);
+        }
+        PauseButton::Fullscreen => {
+            let window = windows.get_primary_mut().unwrap();
+            match window.mode() {
+                bevy::window::WindowMode::Windowed => {
+                    window.set_mode(bevy::window::WindowMode::BorderlessFullscreen)
+                }
+                _ => window.set_mode(bevy::window::WindowMode::Windowed),
+            }
+        }
+        PauseButton::Back => {
+            screen.settings = false;
+            screen.selected = 0;
+        }
+    }
+}
+
+fn keyboard_input_system(
+    mut state: ResMut<State<crate::GameState>>,
+    mut screen: ResMut<Screen>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut windows: ResMut<Windows>,
+    mut save: EventWriter<SaveRequested>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        if screen.settings {
+            screen.settings = false;
+            screen.selected = 0;
+        } else {
+            let _ = state.pop();
+        }
+    } else if keyboard_input.just_released(KeyCode::Down) {
+        screen.selected = usize::min(screen.buttons().len() - 1, screen.selected + 1);
+    } else if keyboard_input.just_released(KeyCode::Up) {
+        screen.selected = screen.selected.saturating_sub(1);
+    } else if keyboard_input.just_pressed(KeyCode::Space)
+        || keyboard_input.just_pressed(KeyCode::Return)
+    {
+        let button = screen.buttons()[screen.selected];
+        activate(button, &mut state, &mut screen, &mut windows, &mut save);
+    }
+}
+
+fn gamepad_input_system(
+    mut state: ResMut<State<crate::GameState>>,
+    mut screen: ResMut<Screen>,
+    gamepads: Res<Gamepads>,
+    mut gamepad_input: ResMut<Input<GamepadButton>>,
+    mut windows: ResMut<Windows>,
+    mut save: EventWriter<SaveRequested>,
+) {
+    for gamepad in gamepads.iter() {
+        let button = |button_type| GamepadButton::new(*gamepad, button_type);
+        if gamepad_input.just_pressed(button(GamepadButtonType::Start)) {
+            gamepad_input.reset(button(GamepadButtonType::Start));
+            let _ = state.pop();
+        } else if gamepad_input.just_released(button(GamepadButtonType::DPadDown)) {
+            screen.selected = usize::min(screen.buttons().len() - 1, screen.selected + 1);
+        } else if gamepad_input.just_released(button(GamepadButtonType::DPadUp)) {
+            screen.selected = screen.selected.saturating_sub(1);
+        } else if gamepad_input.just_pressed(button(GamepadButtonType::South)) {
+            let selected = screen.buttons()[screen.selected];
+            activate(selected, &mut state, &mut screen, &mut windows, &mut save);
+        }
+    }
+}
+
+fn button_system(
+    mut state: ResMut<State<crate::GameState>>,
+    mut screen: ResMut<Screen>,
+    interaction_query: Query<(&Interaction, &ButtonId<PauseButton>), Changed<Interaction>>,
+    mut windows: ResMut<Windows>,
+    mut save: EventWriter<SaveRequested>,
+) {
+    for (interaction, button_id) in interaction_query.iter() {
+        match *interaction {
+            Interaction::Clicked => activate(
+                button_id.0,
+                &mut state,
+                &mut screen,
+                &mut windows,
+                &mut save,
+            ),
+            Interaction::Hovered => {
+                if let Some(index) = screen.buttons().iter().position(|b| *b == button_id.0) {
+                    screen.selected = index;
+                }
+            }
+            Interaction::None => (),
+        }
+    }
+}
+
+fn display_selected(screen: Res<Screen>, mut texts: Query<(&mut Text, &ButtonText<PauseButton>)>) {
+    let selected = screen.buttons()[screen.selected];
+    for (mut text, button) in &mut texts {
+        text.sections[0].style.color = if button.0 == selected {
+            ColorScheme::TEXT
+        } else {
+            ColorScheme::TEXT_DARK
+        };
+    }
+}